@@ -0,0 +1,357 @@
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, Utc};
+
+/// Nepal Standard Time, UTC+05:45.
+pub const NEPAL_OFFSET_SECS: i32 = 5 * 3600 + 45 * 60;
+
+/// First Bikram Sambat year of [`MONTH_DAYS`].
+const FIRST_YEAR: i32 = 2000;
+
+/// Baisakh 1, 2000 BS.
+const FIRST_DAY_AD: (i32, u32, u32) = (1943, 4, 14);
+
+/// First Bikram Sambat year of the content we crawl (2013 AD). Numeric years
+/// before it are read as Gregorian when nothing says otherwise, those from it
+/// on as Bikram Sambat; the two calendars do not meet there until 2070 AD.
+const MODERN_BS_YEAR: i32 = 2070;
+
+/// Days of each month, Baisakh to Chaitra, of the years from [`FIRST_YEAR`].
+/// The calendar follows the sun's passage through the signs and is set
+/// by the Nepal Panchanga Nirnayak Samiti, so there is no formula; these are
+/// the published lengths. Years not published yet hold the usual forecast and
+/// may be a day off within the year.
+const MONTH_DAYS: [[u8; 12]; 91] = [
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2000
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2001
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2002
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2003
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2004
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2005
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2006
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2007
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 29, 31], // 2008
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2009
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2010
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2011
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 30, 30], // 2012
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2013
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2014
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2015
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 30, 30], // 2016
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2017
+    [31, 32, 31, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2018
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2019
+    [31, 31, 31, 32, 31, 31, 30, 29, 30, 29, 30, 30], // 2020
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2021
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 30], // 2022
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2023
+    [31, 31, 31, 32, 31, 31, 30, 29, 30, 29, 30, 30], // 2024
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2025
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2026
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2027
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2028
+    [31, 31, 32, 31, 32, 30, 30, 29, 30, 29, 30, 30], // 2029
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2030
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2031
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2032
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2033
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2034
+    [30, 32, 31, 32, 31, 31, 29, 30, 30, 29, 29, 31], // 2035
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2036
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2037
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2038
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 30, 30], // 2039
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2040
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2041
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2042
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 30, 30], // 2043
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2044
+    [31, 32, 31, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2045
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2046
+    [31, 31, 31, 32, 31, 31, 30, 29, 30, 29, 30, 30], // 2047
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2048
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 30], // 2049
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2050
+    [31, 31, 31, 32, 31, 31, 30, 29, 30, 29, 30, 30], // 2051
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2052
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 30], // 2053
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2054
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2055
+    [31, 31, 32, 31, 32, 30, 30, 29, 30, 29, 30, 30], // 2056
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2057
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2058
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2059
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2060
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2061
+    [30, 32, 31, 32, 31, 31, 29, 30, 29, 30, 29, 31], // 2062
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2063
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2064
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2065
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 29, 31], // 2066
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2067
+    [31, 31, 32, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2068
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2069
+    [31, 31, 31, 32, 31, 31, 29, 30, 30, 29, 30, 30], // 2070
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2071
+    [31, 32, 31, 32, 31, 30, 30, 29, 30, 29, 30, 30], // 2072
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 31], // 2073
+    [31, 31, 31, 32, 31, 31, 30, 29, 30, 29, 30, 30], // 2074
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2075
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 30], // 2076
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 30, 29, 31], // 2077
+    [31, 31, 31, 32, 31, 31, 30, 29, 30, 29, 30, 30], // 2078
+    [31, 31, 32, 31, 31, 31, 30, 29, 30, 29, 30, 30], // 2079
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 29, 30, 30], // 2080
+    [31, 32, 31, 32, 31, 30, 30, 30, 29, 30, 30, 30], // 2081
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 30, 30], // 2082
+    [31, 31, 32, 31, 31, 30, 30, 30, 29, 30, 30, 30], // 2083
+    [31, 31, 32, 31, 31, 30, 30, 30, 29, 30, 30, 30], // 2084
+    [31, 32, 31, 32, 30, 31, 30, 30, 29, 30, 30, 30], // 2085
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 30, 30], // 2086
+    [31, 31, 32, 31, 31, 31, 30, 30, 29, 30, 30, 30], // 2087
+    [30, 31, 32, 32, 30, 31, 30, 30, 29, 30, 30, 30], // 2088
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 30, 30], // 2089
+    [30, 32, 31, 32, 31, 30, 30, 30, 29, 30, 30, 30], // 2090
+];
+
+/// Month names in Devanagari and the Latin spellings Nepali sites use, Baisakh first.
+const MONTH_NAMES: [&[&str]; 12] = [
+    &["बैशाख", "वैशाख", "बैसाख", "baisakh", "baishakh", "vaisakh", "vaishakh"],
+    &["जेठ", "जेष्ठ", "ज्येष्ठ", "jestha", "jeth", "jeshtha", "jyeshtha"],
+    &["असार", "आषाढ", "अषाढ", "asar", "asadh", "ashad", "ashadh", "ashar"],
+    &["साउन", "श्रावण", "सावन", "shrawan", "srawan", "shravan", "sawan", "saun", "saaun"],
+    &["भदौ", "भाद्र", "bhadra", "bhadau", "bhado"],
+    &["असोज", "आश्विन", "असौज", "asoj", "ashoj", "ashwin", "aswin"],
+    &["कात्तिक", "कार्तिक", "कातिक", "kartik", "kattik"],
+    &["मंसिर", "मङ्सिर", "मार्ग", "मङ्गसिर", "mangsir", "mangshir", "marga", "mansir"],
+    &["पुस", "पौष", "पुष", "poush", "paush", "pus", "pous"],
+    &["माघ", "magh", "maagh"],
+    &["फागुन", "फाल्गुन", "falgun", "phalgun", "fagun", "phagun"],
+    &["चैत", "चैत्र", "chaitra", "chait", "chaitr"],
+];
+
+/// A date in the Bikram Sambat calendar, the official calendar of Nepal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BsDate {
+    pub year: i32,
+    /// 1 for Baisakh to 12 for Chaitra
+    pub month: u32,
+    pub day: u32,
+}
+
+impl BsDate {
+    /// `None` if the date does not exist or is outside the known years.
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        let days = month_days(year, month)?;
+        (1..=days).contains(&day).then_some(Self { year, month, day })
+    }
+
+    /// The Gregorian date of the same day.
+    pub fn to_ad(self) -> Option<NaiveDate> {
+        let mut days: u64 = (FIRST_YEAR..self.year)
+            .map(|year| year_days(year) as u64)
+            .sum();
+        days += (1..self.month).filter_map(|month| month_days(self.year, month)).map(u64::from).sum::<u64>();
+        days += u64::from(self.day - 1);
+        first_day().checked_add_days(Days::new(days))
+    }
+
+    /// The Bikram Sambat date of a Gregorian one.
+    pub fn from_ad(date: NaiveDate) -> Option<Self> {
+        let mut days = u32::try_from((date - first_day()).num_days()).ok()?;
+        for (i, months) in MONTH_DAYS.iter().enumerate() {
+            for (m, &length) in months.iter().enumerate() {
+                let length = u32::from(length);
+                if days < length {
+                    return Some(Self { year: FIRST_YEAR + i as i32, month: m as u32 + 1, day: days + 1 });
+                }
+                days -= length;
+            }
+        }
+        None
+    }
+
+    /// The first Bikram Sambat date written in `text`, e.g. "२०८१ असार १५ गते",
+    /// "असार १५, २०८१", "15 Asar 2081" or "२०८१/०३/१५". All-numeric dates and
+    /// Latin month spellings, which may be English words like "pus", only
+    /// count when the year is too far ahead to be a Gregorian one, since both
+    /// calendars use four-digit years in the 2000s.
+    pub fn find_in(text: &str) -> Option<Self> {
+        let normalized = devanagari_to_ascii_digits(text).to_lowercase();
+        let tokens: Vec<&str> = normalized
+            .split(|c: char| c.is_whitespace() || matches!(c, ',' | '।' | '|' | '(' | ')' | ':' | ';'))
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        let number = |i: usize| tokens.get(i).and_then(|t| t.parse::<u32>().ok());
+        let year = |i: usize| number(i).filter(|n| (1000..10000).contains(n)).map(|n| n as i32);
+        let day = |i: usize| number(i).filter(|n| (1..=32).contains(n));
+
+        for (i, token) in tokens.iter().enumerate() {
+            if let Some(month) = month_number(token) {
+                let year = |i: usize| year(i).filter(|&y| !token.is_ascii() || !could_be_gregorian(y));
+                let before = i.checked_sub(1);
+                let candidates = [
+                    // 2081 असार 15
+                    before.and_then(year).zip(day(i + 1)),
+                    // असार 15, 2081
+                    year(i + 2).zip(day(i + 1)),
+                    // 15 असार 2081
+                    year(i + 1).zip(before.and_then(day)),
+                ];
+                if let Some(date) = candidates.into_iter().flatten().find_map(|(y, d)| Self::new(y, month, d)) {
+                    return Some(date);
+                }
+            } else if let Some(date) = Self::parse_numeric(token) {
+                return Some(date);
+            }
+        }
+        None
+    }
+
+    /// `2081-03-15`, `2081/03/15` or `2081.03.15`, if the year cannot be Gregorian.
+    fn parse_numeric(token: &str) -> Option<Self> {
+        let mut parts = token.split(['-', '/', '.']).map(|part| part.parse::<u32>().ok());
+        let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+        if parts.next().is_some() || could_be_gregorian(year as i32) {
+            return None;
+        }
+        Self::new(year as i32, month, day)
+    }
+}
+
+/// Start of `date` in Nepal, for dates printed without a time.
+pub fn nepal_midnight(date: NaiveDate) -> Option<DateTime<Utc>> {
+    let nepal = FixedOffset::east_opt(NEPAL_OFFSET_SECS)?;
+    date.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(nepal)
+        .single()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Replaces Devanagari digits (०-९) with ASCII ones.
+pub fn devanagari_to_ascii_digits(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '०'..='९' => char::from(b'0' + (c as u32 - '०' as u32) as u8),
+            _ => c,
+        })
+        .collect()
+}
+
+pub(crate) fn could_be_gregorian(year: i32) -> bool {
+    year < MODERN_BS_YEAR
+}
+
+fn first_day() -> NaiveDate {
+    let (year, month, day) = FIRST_DAY_AD;
+    NaiveDate::from_ymd_opt(year, month, day).expect("valid epoch")
+}
+
+fn month_days(year: i32, month: u32) -> Option<u32> {
+    let months = MONTH_DAYS.get(usize::try_from(year - FIRST_YEAR).ok()?)?;
+    months.get(usize::try_from(month).ok()?.checked_sub(1)?).map(|&days| u32::from(days))
+}
+
+fn year_days(year: i32) -> u32 {
+    (1..=12).filter_map(|month| month_days(year, month)).sum()
+}
+
+fn month_number(token: &str) -> Option<u32> {
+    // Drop a trailing "को" or "मा", as in "असारको" or "असारमा"
+    let token = token.trim_end_matches("को").trim_end_matches("मा");
+    MONTH_NAMES.iter()
+        .position(|names| names.contains(&token))
+        .map(|i| i as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ad(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn bs(year: i32, month: u32, day: u32) -> BsDate {
+        BsDate::new(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn new_year_days() {
+        for (year, date) in [
+            (2000, ad(1943, 4, 14)),
+            (2070, ad(2013, 4, 14)),
+            (2073, ad(2016, 4, 13)),
+            (2077, ad(2020, 4, 13)),
+            (2080, ad(2023, 4, 14)),
+            (2081, ad(2024, 4, 13)),
+            (2082, ad(2025, 4, 14)),
+        ] {
+            assert_eq!(bs(year, 1, 1).to_ad(), Some(date), "Baisakh 1, {}", year);
+        }
+    }
+
+    #[test]
+    fn asar_15() {
+        for (year, date) in [
+            (2078, ad(2021, 6, 29)),
+            (2079, ad(2022, 6, 29)),
+            (2080, ad(2023, 6, 30)),
+            (2081, ad(2024, 6, 29)),
+        ] {
+            assert_eq!(bs(year, 3, 15).to_ad(), Some(date), "Asar 15, {}", year);
+        }
+    }
+
+    #[test]
+    fn known_dates() {
+        // Democracy Day, Constitution of 2047, Loktantra Day, Republic Day,
+        // the Gorkha earthquake and Constitution Day
+        for (date, expected) in [
+            (bs(2007, 11, 7), ad(1951, 2, 18)),
+            (bs(2047, 7, 23), ad(1990, 11, 9)),
+            (bs(2063, 1, 11), ad(2006, 4, 24)),
+            (bs(2065, 2, 15), ad(2008, 5, 28)),
+            (bs(2072, 1, 12), ad(2015, 4, 25)),
+            (bs(2072, 6, 3), ad(2015, 9, 20)),
+            (bs(2080, 4, 1), ad(2023, 7, 17)),
+            (bs(2081, 4, 1), ad(2024, 7, 16)),
+            (bs(2081, 10, 1), ad(2025, 1, 14)),
+        ] {
+            assert_eq!(date.to_ad(), Some(expected), "{:?}", date);
+            assert_eq!(BsDate::from_ad(expected), Some(date));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for year in FIRST_YEAR..FIRST_YEAR + MONTH_DAYS.len() as i32 {
+            for month in 1..=12 {
+                for day in 1..=month_days(year, month).unwrap() {
+                    let date = bs(year, month, day);
+                    assert_eq!(date.to_ad().and_then(BsDate::from_ad), Some(date));
+                }
+            }
+        }
+        assert_eq!(BsDate::from_ad(ad(1943, 4, 13)), None);
+        assert_eq!(BsDate::new(2081, 2, 33), None);
+    }
+
+    #[test]
+    fn finds_dates_in_text() {
+        assert_eq!(BsDate::find_in("२०८१ असार १५ गते"), Some(bs(2081, 3, 15)));
+        assert_eq!(BsDate::find_in("असार १५, २०८१"), Some(bs(2081, 3, 15)));
+        assert_eq!(BsDate::find_in("15 Asar 2081"), Some(bs(2081, 3, 15)));
+        assert_eq!(BsDate::find_in("२०८१/०३/१५"), Some(bs(2081, 3, 15)));
+        assert_eq!(BsDate::find_in("असारमा १५, २०२४"), Some(bs(2024, 3, 15)));
+    }
+
+    #[test]
+    fn gregorian_years_are_not_read_as_bikram_sambat() {
+        assert_eq!(BsDate::find_in("2024/03/15"), None);
+        assert_eq!(BsDate::find_in("saun 15, 2024"), None);
+        assert_eq!(BsDate::find_in("pus 2024 12"), None);
+        assert_eq!(BsDate::find_in("2069/03/15"), None);
+        assert_eq!(BsDate::find_in("2070/03/15"), BsDate::new(2070, 3, 15));
+    }
+}
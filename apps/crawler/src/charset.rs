@@ -0,0 +1,74 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// How far into the body to look for a `<meta charset>` declaration.
+const META_PRESCAN_BYTES: usize = 4096;
+
+/// Decodes a response body to text, picking the encoding the way browsers
+/// do: byte order mark, then the `Content-Type` charset, then a `<meta>`
+/// declaration, and finally a statistical guess over the bytes themselves.
+/// Returns the text and the encoding that was used.
+pub fn decode(bytes: &[u8], content_type: Option<&str>, url: &str) -> (String, &'static Encoding) {
+    let encoding = detect(bytes, content_type, url);
+    let (text, _, _) = encoding.decode(bytes);
+    (text.into_owned(), encoding)
+}
+
+pub fn detect(bytes: &[u8], content_type: Option<&str>, url: &str) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    if let Some(encoding) = content_type.and_then(header_charset) {
+        return encoding;
+    }
+
+    if let Some(encoding) = meta_charset(bytes) {
+        return encoding;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let tld = url::Url::parse(url).ok()
+        .and_then(|u| u.host_str().and_then(|h| h.rsplit('.').next()).map(|t| t.to_lowercase()));
+    detector.guess(tld.as_deref().map(str::as_bytes), true)
+}
+
+/// `charset` parameter of a `Content-Type` header value.
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes()))
+}
+
+/// Charset from `<meta charset="...">` or `<meta http-equiv="Content-Type" content="...; charset=...">`.
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(META_PRESCAN_BYTES)];
+    let head = String::from_utf8_lossy(head).to_lowercase();
+
+    for tag in head.split("<meta").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let Some((_, rest)) = tag.split_once("charset") else {
+            continue;
+        };
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let label: String = value.trim_start()
+            .trim_start_matches(['"', '\''])
+            .chars()
+            .take_while(|c| !matches!(c, '"' | '\'' | ';' | '/' | '>') && !c.is_whitespace())
+            .collect();
+
+        if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+            // A meta tag can't really declare UTF-16, it would not have been readable as ASCII
+            if encoding == UTF_16LE || encoding == UTF_16BE {
+                return Some(UTF_8);
+            }
+            return Some(encoding);
+        }
+    }
+    None
+}
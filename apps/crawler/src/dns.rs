@@ -0,0 +1,91 @@
+use dashmap::DashMap;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a resolved address is reused.
+const DNS_TTL: Duration = Duration::from_secs(300);
+
+/// How long a failed lookup is remembered, so a dead host is not looked up on every request.
+const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+struct Resolved {
+    /// The addresses, or the kind and message of the lookup error
+    addrs: Result<Arc<Vec<IpAddr>>, (io::ErrorKind, String)>,
+    at: Instant,
+}
+
+/// Caching DNS resolver shared by the fetcher and politeness.
+///
+/// The HTTP client resolves through it, so politeness sees the same
+/// addresses the requests actually go to, and a host is looked up once
+/// per TTL rather than on every connection.
+#[derive(Clone, Default)]
+pub struct DnsCache {
+    entries: Arc<DashMap<String, Resolved>>,
+}
+
+impl DnsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Addresses of `host`, from the cache while it is fresh.
+    pub async fn resolve(&self, host: &str) -> io::Result<Arc<Vec<IpAddr>>> {
+        if let Some(cached) = self.cached(host) {
+            return cached;
+        }
+
+        let addrs = match tokio::net::lookup_host((host, 0)).await {
+            Ok(addrs) => {
+                let addrs: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+                if addrs.is_empty() {
+                    Err((io::ErrorKind::NotFound, format!("no addresses for {}", host)))
+                } else {
+                    Ok(Arc::new(addrs))
+                }
+            }
+            Err(e) => Err((e.kind(), e.to_string())),
+        };
+        self.entries.insert(host.to_string(), Resolved { addrs: addrs.clone(), at: Instant::now() });
+        addrs.map_err(|(kind, message)| io::Error::new(kind, message))
+    }
+
+    /// The cached lookup of `host` if it is still fresh, without resolving.
+    pub fn cached(&self, host: &str) -> Option<io::Result<Arc<Vec<IpAddr>>>> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            return Some(Ok(Arc::new(vec![ip])));
+        }
+        let entry = self.entries.get(host)?;
+        let ttl = if entry.addrs.is_ok() { DNS_TTL } else { NEGATIVE_TTL };
+        if entry.at.elapsed() >= ttl {
+            return None;
+        }
+        Some(entry.addrs.clone().map_err(|(kind, message)| io::Error::new(kind, message)))
+    }
+
+    /// Resolves `host` in the background unless a fresh lookup is cached.
+    pub fn prefetch(&self, host: &str) {
+        if self.cached(host).is_some() {
+            return;
+        }
+        let cache = self.clone();
+        let host = host.to_string();
+        tokio::spawn(async move {
+            let _ = cache.resolve(&host).await;
+        });
+    }
+}
+
+impl Resolve for DnsCache {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cache = self.clone();
+        Box::pin(async move {
+            let ips = cache.resolve(name.as_str()).await?;
+            let addrs: Addrs = Box::new(ips.iter().map(|ip| SocketAddr::new(*ip, 0)).collect::<Vec<_>>().into_iter());
+            Ok(addrs)
+        })
+    }
+}
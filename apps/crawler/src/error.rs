@@ -19,12 +19,83 @@ pub enum CrawlerError {
 
     #[error("Queue error: {0}")]
     Queue(#[from] lapin::Error),
+
+    #[error("Broker rejected message for {0}")]
+    Rejected(String),
     
     #[error("Redis error: {0}")]
     Redis(String),
 
+    #[error("HTTP {0}")]
+    Http(reqwest::StatusCode),
+
+    #[error("Response body over {0} bytes")]
+    TooLarge(u64),
+
+    #[error("Robots.txt unavailable, retry in {0:?}")]
+    RobotsUnavailable(std::time::Duration),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
 
+impl CrawlerError {
+    /// True for failures that may go away on their own, such as timeouts,
+    /// dropped connections, 429 and 5xx. A missing page, an unknown domain or
+    /// a bad certificate will fail the same way next time.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CrawlerError::Network(e) => {
+                if e.is_builder() || e.is_redirect() || e.is_decode() || is_permanent_network_error(e) {
+                    return false;
+                }
+                e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+            }
+            CrawlerError::Http(status) => {
+                *status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || status.is_server_error()
+            }
+            CrawlerError::Database(_)
+            | CrawlerError::Io(_)
+            | CrawlerError::Queue(_)
+            | CrawlerError::Rejected(_)
+            | CrawlerError::Redis(_)
+            | CrawlerError::RobotsUnavailable(_) => true,
+            CrawlerError::Config(_)
+            | CrawlerError::Parse(_)
+            | CrawlerError::TooLarge(_)
+            | CrawlerError::Unknown(_) => false,
+        }
+    }
+}
+
+/// NXDOMAIN and TLS certificate failures surface as connect errors, so they
+/// are told apart by the messages down the source chain.
+fn is_permanent_network_error(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        let message = cause.to_string().to_lowercase();
+        // EAI_AGAIN, the resolver itself is having trouble
+        if message.contains("temporary failure") {
+            return false;
+        }
+        if message.contains("failed to lookup address")
+            || message.contains("no addresses for")
+            || message.contains("name or service not known")
+            || message.contains("certificate")
+        {
+            return true;
+        }
+        source = cause.source();
+    }
+    false
+}
+
+impl From<fred::error::Error> for CrawlerError {
+    fn from(e: fred::error::Error) -> Self {
+        CrawlerError::Redis(e.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, CrawlerError>;
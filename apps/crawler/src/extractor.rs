@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::charset;
+use crate::error::{CrawlerError, Result};
+use crate::legacy_font::PreetiConverter;
+use crate::metadata::PageMetadata;
+use crate::parser::{ParsedPage, Parser, RobotsDirectives};
+
+/// Longest title taken from the first line of a document without markup.
+const MAX_DERIVED_TITLE_CHARS: usize = 200;
+
+/// What an [`Extractor`] got out of one response body.
+#[derive(Debug)]
+pub struct Extraction {
+    pub page: ParsedPage,
+    /// Charset the body was decoded from, for text formats
+    pub encoding: Option<&'static str>,
+}
+
+/// Turns the raw body of one content type into indexable text.
+pub trait Extractor: Send + Sync {
+    fn extract(&self, body: &[u8], content_type: Option<&str>, url: &str) -> Result<Extraction>;
+}
+
+/// HTML and XHTML, through [`Parser`].
+pub struct HtmlExtractor {
+    parser: Parser,
+}
+
+impl HtmlExtractor {
+    pub fn new(parser: Parser) -> Self {
+        Self { parser }
+    }
+}
+
+impl Extractor for HtmlExtractor {
+    fn extract(&self, body: &[u8], content_type: Option<&str>, url: &str) -> Result<Extraction> {
+        let (html, encoding) = charset::decode(body, content_type, url);
+        Ok(Extraction {
+            page: self.parser.parse(&html, url)?,
+            encoding: Some(encoding.name()),
+        })
+    }
+}
+
+/// PDF documents such as gazettes and budget speeches. Many government PDFs
+/// are typeset in Preeti, so lines that look like it are converted.
+pub struct PdfExtractor;
+
+impl Extractor for PdfExtractor {
+    fn extract(&self, body: &[u8], _content_type: Option<&str>, _url: &str) -> Result<Extraction> {
+        let raw = pdf_extract::extract_text_from_mem(body)
+            .map_err(|e| CrawlerError::Parse(format!("PDF: {}", e)))?;
+
+        let text = raw.lines()
+            .map(|line| {
+                if PreetiConverter::looks_like_preeti(line) {
+                    PreetiConverter::convert(line)
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(Extraction { page: text_page(&text), encoding: None })
+    }
+}
+
+/// `text/plain`, indexed as is.
+pub struct PlainTextExtractor;
+
+impl Extractor for PlainTextExtractor {
+    fn extract(&self, body: &[u8], content_type: Option<&str>, url: &str) -> Result<Extraction> {
+        let (text, encoding) = charset::decode(body, content_type, url);
+        Ok(Extraction { page: text_page(&text), encoding: Some(encoding.name()) })
+    }
+}
+
+/// Picks the extractor for a response by its media type.
+///
+/// Types without a registered extractor are not indexed; the spider records
+/// them as skipped. More formats can be plugged in with [`ContentRouter::register`].
+#[derive(Clone)]
+pub struct ContentRouter {
+    extractors: HashMap<String, Arc<dyn Extractor>>,
+}
+
+impl ContentRouter {
+    pub fn new(parser: Parser) -> Self {
+        let html: Arc<dyn Extractor> = Arc::new(HtmlExtractor::new(parser));
+        let pdf: Arc<dyn Extractor> = Arc::new(PdfExtractor);
+
+        let mut router = Self { extractors: HashMap::new() };
+        router.register("text/html", html.clone());
+        router.register("application/xhtml+xml", html);
+        router.register("application/pdf", pdf.clone());
+        router.register("application/x-pdf", pdf);
+        router.register("text/plain", Arc::new(PlainTextExtractor));
+        router
+    }
+
+    pub fn register(&mut self, mime_type: &str, extractor: Arc<dyn Extractor>) {
+        self.extractors.insert(mime_type.to_lowercase(), extractor);
+    }
+
+    pub fn extractor_for(&self, mime_type: &str) -> Option<Arc<dyn Extractor>> {
+        self.extractors.get(mime_type).cloned()
+    }
+}
+
+/// Media type of a response, e.g. `text/html`, without parameters. Servers
+/// often send PDFs as `application/octet-stream` or with no type at all, so
+/// those are sniffed; an untyped body that is not a PDF is taken for HTML.
+pub fn mime_type(content_type: Option<&str>, body: &[u8]) -> String {
+    let declared = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_lowercase())
+        .filter(|ct| !ct.is_empty());
+
+    match declared.as_deref() {
+        None | Some("application/octet-stream") | Some("binary/octet-stream") if body.starts_with(b"%PDF-") => {
+            "application/pdf".to_string()
+        }
+        None => "text/html".to_string(),
+        Some(mime) => mime.to_string(),
+    }
+}
+
+/// A page for formats without markup: the first line doubles as the title.
+fn text_page(text: &str) -> ParsedPage {
+    let title = text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.chars().take(MAX_DERIVED_TITLE_CHARS).collect())
+        .unwrap_or_default();
+
+    ParsedPage {
+        title,
+        links: vec![],
+        text_content: text.split_whitespace().collect::<Vec<_>>().join(" "),
+        main_content: text.split_whitespace().collect::<Vec<_>>().join(" "),
+        canonical_url: None,
+        feeds: vec![],
+        robots: RobotsDirectives::default(),
+        metadata: PageMetadata::default(),
+        lang: None,
+    }
+}
@@ -0,0 +1,35 @@
+use url::Url;
+use crate::error::{CrawlerError, Result};
+
+/// `type` values of `<link rel="alternate">` that point to a feed.
+pub const FEED_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+
+/// Article links of an RSS, Atom or JSON feed, newest first as the feed lists them.
+pub fn item_links(body: &[u8], feed_url: &str) -> Result<Vec<String>> {
+    let feed = feed_rs::parser::Builder::new()
+        .base_uri(Some(feed_url))
+        .build()
+        .parse(body)
+        .map_err(|e| CrawlerError::Parse(format!("Feed: {}", e)))?;
+    let base = Url::parse(feed_url).map_err(|e| CrawlerError::Parse(e.to_string()))?;
+
+    let links = feed.entries.iter()
+        .filter_map(|entry| {
+            // The article itself, not enclosures or comment threads
+            let link = entry.links.iter()
+                .find(|l| l.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+                .or_else(|| entry.links.first())
+                .map(|l| l.href.as_str())
+                // RSS items with only a permalink <guid>
+                .or_else(|| entry.id.starts_with("http").then_some(entry.id.as_str()))?;
+            base.join(link.trim()).ok()
+        })
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(|url| url.to_string())
+        .collect();
+    Ok(links)
+}
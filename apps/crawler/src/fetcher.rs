@@ -1,38 +1,415 @@
-use reqwest::{Client, StatusCode};
-use std::time::Duration;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderMap, HeaderName, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION, RETRY_AFTER};
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response, StatusCode, Version};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use crate::config::AppConfig;
-use crate::error::Result;
-use tracing::info;
+use crate::dns::DnsCache;
+use crate::error::{CrawlerError, Result};
+use crate::frontier::host_of;
+use tracing::{debug, info};
+use url::Url;
 
+/// Redirects followed before the last one is returned as the response.
+const MAX_REDIRECTS: usize = 10;
+
+/// How long an origin whose HTTP/2 broke is spoken to over HTTP/1.1 before h2 is tried again.
+const HTTP1_PIN: Duration = Duration::from_secs(6 * 3600);
+
+/// Decides whether a redirect to another host is followed, e.g. after
+/// checking its robots.txt. `Ok(false)` returns the redirect itself as the response.
+pub type HopCheck = dyn Fn(String) -> BoxFuture<'static, Result<bool>> + Send + Sync;
+
+/// Response headers kept on a [`FetchResult`] besides the ones it has fields for.
+const KEPT_HEADERS: &[&str] = &[
+    "cache-control",
+    "content-encoding",
+    "content-language",
+    "content-length",
+    "date",
+    "expires",
+    "link",
+    "server",
+    "x-robots-tag",
+];
+
+/// HTTP cache validators remembered per URL for conditional recrawls.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            etag: header_string(headers, ETAG),
+            last_modified: header_string(headers, LAST_MODIFIED),
+        }
+    }
+}
+
+/// One hop of a redirect chain: `url` answered with `status` and a `Location`.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub url: String,
+    pub status: StatusCode,
+}
+
+/// A fetched response. The body is kept as bytes, decoding is up to the
+/// extractor for its content type.
+#[derive(Debug)]
+pub struct FetchResult {
+    /// URL the response came from, after redirects
+    pub final_url: String,
+    /// Redirects followed to get there, starting with the requested URL
+    pub redirects: Vec<Redirect>,
+    pub status: StatusCode,
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    /// Validators of this response, to be stored for the next crawl
+    pub validators: Validators,
+    /// The [`KEPT_HEADERS`] present on the response, keyed by lowercase name,
+    /// with every value of a repeated header in order.
+    pub headers: HashMap<String, Vec<String>>,
+    /// Time until the final response headers arrived, redirects included
+    pub ttfb: Duration,
+    /// Time until the body was read
+    pub total_time: Duration,
+    /// Address of the server that sent the final response
+    pub remote_addr: Option<SocketAddr>,
+    /// `Retry-After` of a 429 or 503
+    pub retry_after: Option<Duration>,
+    /// HTTP version the response came over
+    pub version: Version,
+    /// The body was cut off at the size limit
+    pub truncated: bool,
+}
+
+impl FetchResult {
+    /// The requested URL, before any redirect.
+    pub fn requested_url(&self) -> &str {
+        self.redirects.first().map_or(self.final_url.as_str(), |r| r.url.as_str())
+    }
+
+    /// A header's value, repeated headers joined with commas.
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers.get(name).map(|values| values.join(", "))
+    }
+
+    /// Each value of a header on its own, for headers like `X-Robots-Tag`
+    /// whose values cannot simply be joined.
+    pub fn header_values(&self, name: &str) -> &[String] {
+        self.headers.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Which HTTP versions responses came over.
+#[derive(Debug, Default)]
+pub struct ProtocolMetrics {
+    pub http1: AtomicU64,
+    pub http2: AtomicU64,
+    /// Requests that failed over HTTP/2 and were sent again over HTTP/1.1
+    pub fallbacks: AtomicU64,
+}
+
+fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+}
+
+fn kept_headers(headers: &HeaderMap) -> HashMap<String, Vec<String>> {
+    KEPT_HEADERS.iter()
+        .filter_map(|&name| {
+            let values: Vec<String> = headers.get_all(name).iter()
+                .filter_map(|v| v.to_str().ok())
+                .map(str::to_string)
+                .collect();
+            (!values.is_empty()).then(|| (name.to_string(), values))
+        })
+        .collect()
+}
+
+/// HTTP client of the crawler.
+///
+/// HTTP/2 is offered through ALPN on TLS connections and HTTP/1.1 is used
+/// wherever the server does not take it up. Some servers accept h2 and then
+/// break on it; requests to those are sent again over HTTP/1.1 and the origin
+/// sticks to HTTP/1.1 for a while.
+///
+/// Redirects are followed here rather than by the client so the chain can be
+/// reported on the result.
 #[derive(Clone)]
 pub struct Fetcher {
     client: Client,
+    http1: Client,
+    dns: DnsCache,
+    /// Origins whose HTTP/2 failed, with the time until which they get HTTP/1.1
+    http1_origins: Arc<DashMap<String, Instant>>,
+    metrics: Arc<ProtocolMetrics>,
+    max_body_bytes: u64,
 }
 
 impl Fetcher {
     pub fn new(config: &AppConfig) -> Result<Self> {
-        let client = Client::builder()
-            .user_agent(&config.user_agent)
-            .timeout(Duration::from_secs(10))
-            .connect_timeout(Duration::from_secs(5))
-            .pool_idle_timeout(Duration::from_secs(90))
-            .pool_max_idle_per_host(config.crawler_concurrency)
-            .http2_prior_knowledge() // Assume HTTP/2 capability or negotiate
-            .build()?; // reqwest error is converted to CrawlerError
+        let dns = DnsCache::new();
+        let client = client_builder(config, &dns).build()?; // reqwest error is converted to CrawlerError
+        let http1 = client_builder(config, &dns).http1_only().build()?;
+
+        Ok(Self {
+            client,
+            http1,
+            dns,
+            http1_origins: Arc::new(DashMap::new()),
+            metrics: Arc::new(ProtocolMetrics::default()),
+            max_body_bytes: config.max_body_bytes,
+        })
+    }
+
+    pub fn protocol_metrics(&self) -> &ProtocolMetrics {
+        &self.metrics
+    }
+
+    /// The resolver requests go through, so politeness can group hosts by address.
+    pub fn dns(&self) -> &DnsCache {
+        &self.dns
+    }
 
-        Ok(Self { client })
+    pub async fn fetch(&self, url: &str) -> Result<FetchResult> {
+        self.fetch_conditional(url, &Validators::default()).await
     }
 
-    pub async fn fetch(&self, url: &str) -> Result<(StatusCode, String)> {
+    /// GET that sends `If-None-Match` / `If-Modified-Since` from earlier validators.
+    /// A `304 Not Modified` comes back with an empty body.
+    pub async fn fetch_conditional(&self, url: &str, validators: &Validators) -> Result<FetchResult> {
+        self.fetch_conditional_with_limit(url, validators, self.max_body_bytes).await
+    }
+
+    /// [`Fetcher::fetch_conditional`] with a body size limit other than the configured one.
+    pub async fn fetch_conditional_with_limit(&self, url: &str, validators: &Validators, max_body_bytes: u64) -> Result<FetchResult> {
+        self.get(url, validators, max_body_bytes, None).await
+    }
+
+    /// [`Fetcher::fetch_conditional`] that only follows a redirect to another
+    /// host once `check` allows it.
+    pub async fn fetch_checked(&self, url: &str, validators: &Validators, check: &HopCheck) -> Result<FetchResult> {
+        self.get(url, validators, self.max_body_bytes, Some(check)).await
+    }
+
+    async fn get(&self, url: &str, validators: &Validators, max_body_bytes: u64, check: Option<&HopCheck>) -> Result<FetchResult> {
         info!("Fetching URL: {}", url);
-        let response = self.client.get(url).send().await?;
+        let requested = url;
+        let request = |client: &Client, url: &str| {
+            let mut request = client.get(url);
+            // The validators belong to the requested URL, not to where it redirects
+            if url != requested {
+                return request;
+            }
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+            request
+        };
+
+        let started = Instant::now();
+        let (response, redirects) = self.send(url, request, check).await?;
+        let ttfb = started.elapsed();
+        let final_url = response.url().to_string();
         let status = response.status();
-        let body = response.text().await?;
-        Ok((status, body))
+        let version = response.version();
+        let remote_addr = response.remote_addr();
+        let headers = kept_headers(response.headers());
+        let validators = Validators::from_headers(response.headers());
+        let content_type = header_string(response.headers(), CONTENT_TYPE);
+        let retry_after = header_string(response.headers(), RETRY_AFTER).and_then(|v| parse_retry_after(&v));
+        let (body, truncated) = if status == StatusCode::NOT_MODIFIED {
+            (Vec::new(), false)
+        } else {
+            read_body(response, max_body_bytes, content_type.as_deref()).await?
+        };
+
+        Ok(FetchResult {
+            final_url,
+            redirects,
+            status,
+            body,
+            content_type,
+            validators,
+            headers,
+            ttfb,
+            total_time: started.elapsed(),
+            remote_addr,
+            retry_after,
+            version,
+            truncated,
+        })
     }
-    
+
     pub async fn fetch_head(&self, url: &str) -> Result<StatusCode> {
-        let response = self.client.head(url).send().await?;
+        let (response, _) = self.send(url, |client, url| client.head(url), None).await?;
         Ok(response.status())
     }
+
+    /// Sends the request built by `request` and follows redirects. A redirect
+    /// that loops, leads off HTTP, exceeds [`MAX_REDIRECTS`] or to another host
+    /// `check` turns down is returned as the response.
+    async fn send(
+        &self,
+        url: &str,
+        request: impl Fn(&Client, &str) -> RequestBuilder,
+        check: Option<&HopCheck>,
+    ) -> Result<(Response, Vec<Redirect>)> {
+        let mut redirects = Vec::new();
+        let mut seen = HashSet::new();
+        let mut current = url.to_string();
+        loop {
+            let response = self.send_once(&current, &request).await?;
+            let status = response.status();
+            let is_redirect = matches!(
+                status,
+                StatusCode::MOVED_PERMANENTLY
+                    | StatusCode::FOUND
+                    | StatusCode::SEE_OTHER
+                    | StatusCode::TEMPORARY_REDIRECT
+                    | StatusCode::PERMANENT_REDIRECT
+            );
+            let next = header_string(response.headers(), LOCATION)
+                .filter(|_| is_redirect && redirects.len() < MAX_REDIRECTS)
+                .and_then(|location| response.url().join(location.trim()).ok())
+                .filter(|next| matches!(next.scheme(), "http" | "https"))
+                .map(|next| next.to_string());
+
+            let next = match (next, check) {
+                (Some(next), Some(check)) if host_of(&next) != host_of(&current) => {
+                    if check(next.clone()).await? {
+                        Some(next)
+                    } else {
+                        debug!("Not following redirect from {} to {}", current, next);
+                        None
+                    }
+                }
+                (next, _) => next,
+            };
+
+            match next {
+                Some(next) if seen.insert(current.clone()) && !seen.contains(&next) => {
+                    debug!("{} {} redirects to {}", status, current, next);
+                    redirects.push(Redirect { url: current, status });
+                    current = next;
+                }
+                _ => return Ok((response, redirects)),
+            }
+        }
+    }
+
+    /// Sends one request over the protocol known to work for the host,
+    /// falling back to HTTP/1.1 if HTTP/2 fails.
+    async fn send_once(&self, url: &str, request: &impl Fn(&Client, &str) -> RequestBuilder) -> Result<Response> {
+        let origin = Url::parse(url).map(|u| u.origin().ascii_serialization()).unwrap_or_default();
+        let now = Instant::now();
+        self.http1_origins.remove_if(&origin, |_, until| *until <= now);
+        let known_http1 = self.http1_origins.contains_key(&origin);
+        let client = if known_http1 { &self.http1 } else { &self.client };
+
+        let response = match request(client, url).send().await {
+            Ok(response) => response,
+            Err(e) if !known_http1 && is_http2_error(&e) => {
+                debug!("HTTP/2 failed for {}, retrying over HTTP/1.1: {}", origin, e);
+                self.metrics.fallbacks.fetch_add(1, Ordering::Relaxed);
+                self.http1_origins.insert(origin, now + HTTP1_PIN);
+                request(&self.http1, url).send().await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let version = response.version();
+        if version >= Version::HTTP_2 {
+            self.metrics.http2.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.metrics.http1.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(response)
+    }
+}
+
+/// Reads the body in chunks up to `limit` bytes. Text formats past the limit
+/// are cut off there, since the beginning of a page is still worth indexing;
+/// anything else (video, archives, PDFs, which are useless when incomplete)
+/// fails with [`CrawlerError::TooLarge`], without downloading it at all when
+/// `Content-Length` already gives it away. Returns the body and whether it was cut.
+async fn read_body(mut response: Response, limit: u64, content_type: Option<&str>) -> Result<(Vec<u8>, bool)> {
+    let truncatable = is_text(content_type);
+    let declared = response.content_length();
+    if declared.is_some_and(|len| len > limit) && !truncatable {
+        return Err(CrawlerError::TooLarge(limit));
+    }
+
+    let capacity = declared.unwrap_or(0).min(limit) as usize;
+    let mut body = Vec::with_capacity(capacity);
+    while let Some(chunk) = response.chunk().await? {
+        let room = limit as usize - body.len();
+        if chunk.len() > room {
+            if !truncatable {
+                return Err(CrawlerError::TooLarge(limit));
+            }
+            // Dropping the response closes the connection instead of draining the rest
+            body.extend_from_slice(&chunk[..room]);
+            return Ok((body, true));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, false))
+}
+
+/// Text based types, including untyped responses which are taken for HTML.
+fn is_text(content_type: Option<&str>) -> bool {
+    let Some(mime) = content_type.and_then(|ct| ct.split(';').next()).map(|ct| ct.trim().to_lowercase()) else {
+        return true;
+    };
+    mime.is_empty()
+        || mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("/xml")
+        || mime.ends_with("json")
+}
+
+fn client_builder(config: &AppConfig, dns: &DnsCache) -> ClientBuilder {
+    Client::builder()
+        .user_agent(&config.user_agent)
+        .timeout(Duration::from_secs(10))
+        .connect_timeout(Duration::from_secs(5))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(config.crawler_concurrency)
+        .dns_resolver(Arc::new(dns.clone()))
+        .redirect(Policy::none())
+}
+
+/// Errors of the HTTP/2 layer itself, e.g. a server that agreed to h2 over
+/// ALPN and then reset the connection or sent malformed frames.
+fn is_http2_error(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        if cause.to_string().contains("http2 error") {
+            return true;
+        }
+        source = cause.source();
+    }
+    false
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value.trim()).ok()?.with_timezone(&Utc);
+    (at - Utc::now()).to_std().ok()
 }
@@ -0,0 +1,333 @@
+use fred::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+use url::Url;
+use crate::error::Result;
+use crate::queue::Broker;
+
+/// Hosts with pending URLs, scored by the time (ms) they may next be served.
+const HOSTS_KEY: &str = "frontier:hosts";
+const INFLIGHT_KEY: &str = "frontier:inflight";
+
+/// Keeps priorities apart in a host queue's score; within a band URLs stay FIFO.
+const PRIORITY_BAND: i64 = 10_000_000_000_000;
+
+/// Adds a URL to its host queue and makes sure the host is scheduled.
+/// An already scheduled host keeps its ready time.
+const PUSH_SCRIPT: &str = r#"
+redis.call('ZADD', KEYS[1], 'NX', ARGV[1], ARGV[2])
+redis.call('ZADD', KEYS[2], 'NX', ARGV[3], ARGV[4])
+"#;
+
+/// Pops the best URL of one host and records its lease in the same step, so
+/// a crash in between can never drop the URL. The host moves to the back of
+/// the ready hosts, or leaves the schedule once its queue is drained.
+const POP_SCRIPT: &str = r#"
+local popped = redis.call('ZPOPMIN', KEYS[1])
+if #popped == 0 then
+    redis.call('ZREM', KEYS[2], ARGV[3])
+    return false
+end
+redis.call('ZADD', KEYS[3], ARGV[1], popped[1])
+if redis.call('ZCARD', KEYS[1]) == 0 then
+    redis.call('ZREM', KEYS[2], ARGV[3])
+else
+    redis.call('ZADD', KEYS[2], 'XX', ARGV[2], ARGV[3])
+end
+return popped[1]
+"#;
+
+/// Takes the members of a sorted set scored at or below ARGV[1] out of it,
+/// at most ARGV[2] of them, in one step so concurrent workers never claim
+/// the same member twice.
+const CLAIM_SCRIPT: &str = r#"
+local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+if #due > 0 then
+    redis.call('ZREM', KEYS[1], unpack(due))
+end
+return due
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High = 0,
+    Normal = 1,
+    Low = 2,
+}
+
+/// A URL handed out by the frontier. It stays leased until it is acked or
+/// released; if neither happens before the deadline it is re-delivered.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub url: String,
+    pub host: String,
+    /// Set when the URL came from RabbitMQ rather than the Redis frontier.
+    delivery_tag: Option<u64>,
+}
+
+/// Redis-backed crawl frontier.
+///
+/// Every host has its own priority queue, and a schedule of hosts records
+/// when each may be served next so one big site cannot crowd out the rest.
+/// Leased URLs live in a sorted set scored by their lease deadline, and seen
+/// URLs are marked with `visited:{url}` keys for good; recrawls are queued
+/// from the [`RecrawlScheduler`](crate::recrawl::RecrawlScheduler) only. All
+/// of it survives restarts, so a new process resumes the previous crawl.
+///
+/// With a [`Broker`] attached the crawl is distributed: new URLs are
+/// published to RabbitMQ instead, and the host schedule covers the shards
+/// this worker consumes. The visited keys stay in Redis, shared by all workers.
+#[derive(Clone)]
+pub struct Frontier {
+    redis: Client,
+    lease_timeout: Duration,
+    broker: Option<Arc<Broker>>,
+}
+
+impl Frontier {
+    pub fn new(redis: Client, lease_timeout: Duration) -> Self {
+        Self { redis, lease_timeout, broker: None }
+    }
+
+    pub fn with_broker(mut self, broker: Broker) -> Self {
+        self.broker = Some(Arc::new(broker));
+        self
+    }
+
+    /// Fails when the broker can no longer deliver URLs.
+    pub fn check_transport(&self) -> Result<()> {
+        match &self.broker {
+            Some(broker) => broker.check(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn lease_timeout(&self) -> Duration {
+        self.lease_timeout
+    }
+
+    /// Adds URLs that have never been seen before at normal priority.
+    /// Returns how many were queued.
+    pub async fn enqueue(&self, urls: &[String]) -> Result<usize> {
+        self.enqueue_with_priority(urls, Priority::Normal).await
+    }
+
+    pub async fn enqueue_with_priority(&self, urls: &[String], priority: Priority) -> Result<usize> {
+        let mut queued = 0;
+        for url in urls {
+            let key = format!("visited:{}", url);
+            let fresh: Option<String> = self.redis
+                .set(&key, "1", None, Some(SetOptions::NX), false)
+                .await?;
+            if fresh.is_some() && self.queue(url, &key, priority).await? {
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Queues URLs due for a recrawl. They were seen before, so their
+    /// visited markers are renewed instead of checked.
+    pub async fn enqueue_recrawl(&self, urls: &[String]) -> Result<usize> {
+        let mut queued = 0;
+        for url in urls {
+            let key = format!("visited:{}", url);
+            self.redis.set::<(), _, _>(&key, "1", None, None, false).await?;
+            if self.queue(url, &key, Priority::Normal).await? {
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Marks a URL as seen without queueing it, e.g. a redirect target that
+    /// was fetched through another URL.
+    pub async fn mark_visited(&self, url: &str) -> Result<()> {
+        self.redis.set::<(), _, _>(format!("visited:{}", url), "1", None, None, false).await?;
+        Ok(())
+    }
+
+    async fn queue(&self, url: &str, visited_key: &str, priority: Priority) -> Result<bool> {
+        let Some(host) = host_of(url) else {
+            return Ok(false);
+        };
+
+        let pushed = match &self.broker {
+            Some(broker) => broker.publish(url, &host, priority).await,
+            None => self.push(&host, url, priority as i64 * PRIORITY_BAND + now_millis()).await,
+        };
+        if let Err(e) = pushed {
+            // Unmark it, otherwise the URL would count as seen without ever being queued
+            self.redis.del::<(), _>(visited_key).await?;
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Hosts whose ready time has passed, longest waiting first.
+    pub async fn ready_hosts(&self, limit: usize) -> Result<Vec<String>> {
+        if let Some(broker) = &self.broker {
+            return Ok(broker.ready_hosts(limit));
+        }
+        let hosts = self.redis
+            .zrangebyscore(HOSTS_KEY, "-inf", now_millis(), false, Some((0, limit as i64)))
+            .await?;
+        Ok(hosts)
+    }
+
+    /// How long until the next scheduled host becomes ready, if any is scheduled.
+    pub async fn next_ready_in(&self) -> Result<Option<Duration>> {
+        if let Some(broker) = &self.broker {
+            return Ok(broker.next_ready_in());
+        }
+        let next: Vec<(String, f64)> = self.redis
+            .zrange(HOSTS_KEY, 0, 0, None, false, None, true)
+            .await?;
+        Ok(next.first().map(|(_, ready_at)| {
+            Duration::from_millis((*ready_at as i64 - now_millis()).max(0) as u64)
+        }))
+    }
+
+    /// Pushes a host's ready time into the future, e.g. while its rate limiter is empty.
+    pub async fn defer_host(&self, host: &str, wait: Duration) -> Result<()> {
+        if let Some(broker) = &self.broker {
+            broker.defer_host(host, wait);
+            return Ok(());
+        }
+        let ready_at = now_millis() + wait.as_millis() as i64;
+        self.redis
+            .zadd::<(), _, _>(HOSTS_KEY, Some(SetOptions::XX), None, false, false, (ready_at as f64, host))
+            .await?;
+        Ok(())
+    }
+
+    /// Leases the best pending URL of `host`, if it has one.
+    pub async fn dequeue_from(&self, host: &str) -> Result<Option<Lease>> {
+        if let Some(broker) = &self.broker {
+            return Ok(broker.dequeue_from(host).map(|(url, tag)| Lease {
+                url,
+                host: host.to_string(),
+                delivery_tag: Some(tag),
+            }));
+        }
+
+        let now = now_millis();
+        let deadline = now + self.lease_timeout.as_millis() as i64;
+        let url: Option<String> = self.redis
+            .eval(
+                POP_SCRIPT,
+                vec![queue_key(host), HOSTS_KEY.to_string(), INFLIGHT_KEY.to_string()],
+                vec![deadline.to_string(), now.to_string(), host.to_string()],
+            )
+            .await?;
+        Ok(url.map(|url| Lease { url, host: host.to_string(), delivery_tag: None }))
+    }
+
+    /// Marks a leased URL as done.
+    pub async fn ack(&self, lease: &Lease) -> Result<()> {
+        if let (Some(broker), Some(tag)) = (&self.broker, lease.delivery_tag) {
+            return broker.ack(tag).await;
+        }
+        self.redis.zrem::<(), _, _>(INFLIGHT_KEY, lease.url.as_str()).await?;
+        Ok(())
+    }
+
+    /// Gives a leased URL back to the front of its host queue.
+    pub async fn release(&self, lease: &Lease) -> Result<()> {
+        if let (Some(broker), Some(tag)) = (&self.broker, lease.delivery_tag) {
+            return broker.release(tag).await;
+        }
+        let removed: i64 = self.redis.zrem(INFLIGHT_KEY, lease.url.as_str()).await?;
+        if removed > 0 {
+            self.push(&lease.host, &lease.url, 0).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves leases whose deadline has passed back to their host queues.
+    /// Their workers are assumed dead, whether in this process or another.
+    /// RabbitMQ does this itself for unacked deliveries of a dead worker.
+    pub async fn requeue_expired(&self) -> Result<usize> {
+        if self.broker.is_some() {
+            return Ok(0);
+        }
+
+        let expired: Vec<String> = self.redis
+            .zrangebyscore(INFLIGHT_KEY, "-inf", now_millis(), false, None)
+            .await?;
+
+        let mut requeued = 0;
+        for url in expired {
+            // ZREM acts as the claim, so only one process requeues each URL
+            let removed: i64 = self.redis.zrem(INFLIGHT_KEY, url.as_str()).await?;
+            if removed > 0 {
+                if let Some(host) = host_of(&url) {
+                    debug!("Lease expired, re-delivering: {}", url);
+                    self.push(&host, &url, 0).await?;
+                    requeued += 1;
+                }
+            }
+        }
+
+        if requeued > 0 {
+            info!("Re-delivered {} URLs with expired leases", requeued);
+        }
+        Ok(requeued)
+    }
+
+    /// Number of hosts that still have pending URLs.
+    pub async fn pending_hosts(&self) -> Result<usize> {
+        if let Some(broker) = &self.broker {
+            return Ok(broker.pending_hosts());
+        }
+        Ok(self.redis.zcard(HOSTS_KEY).await?)
+    }
+
+    pub async fn inflight_len(&self) -> Result<usize> {
+        Ok(self.redis.zcard(INFLIGHT_KEY).await?)
+    }
+
+    /// True when nothing is pending or leased anywhere. A distributed crawl
+    /// is shared with other workers, so it never looks finished from one of them.
+    pub async fn is_empty(&self) -> Result<bool> {
+        if self.broker.is_some() {
+            return Ok(false);
+        }
+        Ok(self.pending_hosts().await? == 0 && self.inflight_len().await? == 0)
+    }
+
+    async fn push(&self, host: &str, url: &str, score: i64) -> Result<()> {
+        self.redis
+            .eval::<(), _, _, _>(
+                PUSH_SCRIPT,
+                vec![queue_key(host), HOSTS_KEY.to_string()],
+                vec![score.to_string(), url.to_string(), now_millis().to_string(), host.to_string()],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn queue_key(host: &str) -> String {
+    format!("frontier:queue:{}", host)
+}
+
+pub(crate) fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(|h| h.to_string())
+}
+
+/// Claims up to `limit` members of the sorted set `key` whose score, a time
+/// in milliseconds, has passed.
+pub(crate) async fn claim_due(redis: &Client, key: &str, limit: usize) -> Result<Vec<String>> {
+    Ok(redis
+        .eval(CLAIM_SCRIPT, vec![key.to_string()], vec![now_millis().to_string(), limit.to_string()])
+        .await?)
+}
+
+pub(crate) fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
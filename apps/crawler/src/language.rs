@@ -0,0 +1,251 @@
+use crate::stemmer::NepaliNlp;
+use std::collections::HashMap;
+
+/// Only the start of long documents is looked at.
+const SAMPLE_CHARS: usize = 20_000;
+
+/// Fewer letters than this say too little about the language; the declared one is taken.
+const MIN_LETTERS: usize = 20;
+
+/// Share of the letters the minority script needs for a page to count as mixed.
+const MIXED_SCRIPT_SHARE: f64 = 0.2;
+
+/// Longest n-gram looked up, in characters including the word boundary marks.
+const MAX_NGRAM_CHARS: usize = 5;
+
+/// Weight of a declared Devanagari language, as a share of the n-gram evidence.
+const DECLARED_BONUS: f64 = 0.25;
+
+/// Character n-grams typical of each Devanagari language and how telling
+/// they are. `_` marks a word boundary, so `_छ_` is the word छ and `ेको_`
+/// a word ending. They are mostly function words and verb endings, which
+/// the languages share the least.
+const PROFILES: &[(Language, &[(&str, f64)])] = &[
+    (Language::Nepali, &[
+        ("_छ_", 3.0), ("_छन्_", 3.0), ("्छ_", 2.0), ("्छन्", 2.0), ("_को_", 1.0), ("_ले_", 2.0),
+        ("_लाई_", 3.0), ("हरू", 3.0), ("_पनि_", 2.0), ("_थियो", 3.0), ("ेको_", 2.0), ("एको_", 2.0),
+        ("_मा_", 1.5), ("_र_", 1.0), ("_गर्", 1.0), ("ँदै_", 2.0), ("_भने_", 2.0), ("_हुने", 1.5),
+    ]),
+    (Language::Hindi, &[
+        ("_है_", 3.0), ("_हैं_", 3.0), ("_का_", 1.0), ("_की_", 2.0), ("_के_", 1.0), ("_में_", 3.0),
+        ("_और_", 3.0), ("_से_", 2.0), ("_ने_", 2.0), ("_था_", 2.0), ("_नहीं", 3.0), ("_लिए", 2.0),
+        ("_गया", 2.0), ("_यह_", 2.0), ("_भी_", 2.0), ("_किया", 2.0),
+    ]),
+    (Language::Maithili, &[
+        ("_अछि", 3.0), ("_छथि", 3.0), ("_छल_", 2.0), ("_सँ_", 2.0), ("_क_", 1.5), ("_केर", 2.0),
+        ("_मे_", 2.0), ("_आ_", 1.5), ("_गेल", 2.0), ("_छैक", 3.0), ("_कएल", 3.0), ("_एहि", 2.0),
+        ("_हमर", 2.0), ("ैत_", 1.5), ("_रहल", 1.5),
+    ]),
+    (Language::Newari, &[
+        ("_खः_", 3.0), ("_दु_", 2.0), ("_जुल", 2.0), ("_यागु", 3.0), ("_याः", 3.0), ("_नं_", 1.5),
+        ("_पिं", 2.0), ("_पिनि", 3.0), ("_या_", 1.5), ("_यात", 2.0), ("ाय्_", 2.0), ("_व_", 1.0),
+        ("_नापं", 3.0), ("_जुया", 3.0), ("_थ्व", 3.0), ("_उगु", 2.0), ("ेगु_", 2.0), ("_दुगु", 3.0),
+    ]),
+];
+
+/// Languages the crawler tells apart. Anything else in Latin script is taken as English.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Nepali,
+    Hindi,
+    Maithili,
+    Newari,
+    English,
+}
+
+impl Language {
+    /// ISO 639 code, as stored in `documents.language`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::Nepali => "ne",
+            Language::Hindi => "hi",
+            Language::Maithili => "mai",
+            Language::Newari => "new",
+            Language::English => "en",
+        }
+    }
+
+    /// Language of a tag like `ne-NP` or `en_US`. `np` is a common mistake for Nepali.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_lowercase();
+        match primary.as_str() {
+            "ne" | "nep" | "np" => Some(Language::Nepali),
+            "hi" | "hin" => Some(Language::Hindi),
+            "mai" => Some(Language::Maithili),
+            "new" => Some(Language::Newari),
+            "en" | "eng" => Some(Language::English),
+            _ => None,
+        }
+    }
+
+    pub fn is_devanagari(self) -> bool {
+        self != Language::English
+    }
+
+    /// Searchable text of a document in this language. Only Nepali goes
+    /// through the Nepali stemmer; the other Devanagari languages are
+    /// normalized and transliterated without it, English is lowercased.
+    pub fn searchable_text(self, text: &str) -> String {
+        match self {
+            Language::Nepali => NepaliNlp::process_text(text),
+            Language::English => words(text).map(str::to_lowercase).collect::<Vec<_>>().join(" "),
+            _ => {
+                let normalized = NepaliNlp::normalize(text);
+                let mut processed = words(&normalized).collect::<Vec<_>>().join(" ");
+                let transliterated = NepaliNlp::transliterate(text);
+                if !transliterated.is_empty() {
+                    processed.push(' ');
+                    processed.push_str(&transliterated);
+                }
+                processed
+            }
+        }
+    }
+}
+
+/// What a document was identified as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identification {
+    pub language: Language,
+    /// Devanagari and Latin text both make up a good part of the page
+    pub mixed_script: bool,
+}
+
+/// Identifies the language of `text`. `declared` are the page's own claims,
+/// most specific first: the `lang` attribute, the Content-Language header
+/// and the like. The script decides between English and the Devanagari
+/// languages, since pages often keep the `en` of their template; among those
+/// the n-gram profiles decide, nudged by the declaration. Devanagari without
+/// any telling n-grams is taken as Nepali. Returns `None` for text too short
+/// to tell and no usable declaration.
+pub fn identify(text: &str, declared: &[Option<&str>]) -> Option<Identification> {
+    let declared = declared.iter()
+        .flatten()
+        .flat_map(|value| value.split(','))
+        .find_map(Language::from_tag);
+    let sample: String = text.chars().take(SAMPLE_CHARS).collect();
+
+    // Vowel signs count too, as they are written as separate letters in Latin script
+    let devanagari = sample.chars().filter(|c| is_devanagari_letter(*c) || is_devanagari_mark(*c)).count();
+    let latin = sample.chars().filter(|c| c.is_alphabetic() && (c.is_ascii() || ('\u{00C0}'..='\u{024F}').contains(c))).count();
+    let letters = devanagari + latin;
+    if letters < MIN_LETTERS {
+        return declared.map(|language| Identification { language, mixed_script: false });
+    }
+
+    let minority = devanagari.min(latin) as f64 / letters as f64;
+    let mixed_script = minority >= MIXED_SCRIPT_SHARE;
+    let language = if devanagari >= latin {
+        devanagari_language(&sample, declared.filter(|l| l.is_devanagari()))
+    } else {
+        Language::English
+    };
+    Some(Identification { language, mixed_script })
+}
+
+/// Searchable text for a document of `language`. Documents of unknown
+/// language go through the Nepali pipeline, as before identification.
+pub fn searchable_text(text: &str, language: Option<Language>) -> String {
+    language.unwrap_or(Language::Nepali).searchable_text(text)
+}
+
+/// Scores the Devanagari words of `text` against [`PROFILES`].
+fn devanagari_language(text: &str, declared: Option<Language>) -> Language {
+    let weights: HashMap<&str, (Language, f64)> = PROFILES.iter()
+        .flat_map(|(language, ngrams)| ngrams.iter().map(move |(ngram, weight)| (*ngram, (*language, *weight))))
+        .collect();
+
+    let mut scores: HashMap<Language, f64> = HashMap::new();
+    for word in words(text).filter(|w| w.chars().any(is_devanagari_letter)) {
+        let padded: Vec<char> = format!("_{}_", word).chars().collect();
+        for len in 2..=MAX_NGRAM_CHARS.min(padded.len()) {
+            for window in padded.windows(len) {
+                let ngram: String = window.iter().collect();
+                if let Some((language, weight)) = weights.get(ngram.as_str()) {
+                    *scores.entry(*language).or_default() += weight;
+                }
+            }
+        }
+    }
+
+    if let Some(declared) = declared {
+        let evidence: f64 = scores.values().sum();
+        *scores.entry(declared).or_default() += evidence * DECLARED_BONUS;
+    }
+    scores.into_iter()
+        .filter(|(_, score)| *score > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(language, _)| language)
+        .or(declared)
+        .unwrap_or(Language::Nepali)
+}
+
+/// Words of `text`, split on whitespace and punctuation including the danda.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || is_devanagari_mark(c)))
+        .filter(|word| !word.is_empty())
+}
+
+fn is_devanagari_letter(c: char) -> bool {
+    matches!(c, '\u{0904}'..='\u{0939}' | '\u{0958}'..='\u{0961}' | '\u{0972}'..='\u{097F}')
+}
+
+/// Vowel signs, virama, anusvara and the like, which belong to the word they are in.
+fn is_devanagari_mark(c: char) -> bool {
+    matches!(c, '\u{0900}'..='\u{0903}' | '\u{093A}'..='\u{094F}' | '\u{0951}'..='\u{0957}' | '\u{0962}'..='\u{0963}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn language(text: &str, declared: &[Option<&str>]) -> Option<Language> {
+        identify(text, declared).map(|identification| identification.language)
+    }
+
+    #[test]
+    fn tells_the_devanagari_languages_apart() {
+        let nepali = "सरकारले नयाँ बजेट सार्वजनिक गरेको छ। यसमा किसानलाई पनि राहत दिइएको छ भने सडकका लागि थप रकम छुट्याइएको थियो।";
+        let hindi = "सरकार ने नया बजट पेश किया है और इसमें किसानों के लिए कई योजनाएं हैं। यह बजट पिछले साल से बड़ा है।";
+        let maithili = "सरकार नव बजट प्रस्तुत कएलक अछि आ एहि मे किसान सभक लेल बहुत योजना छैक। हमर गाम मे सेहो सड़क बनि रहल अछि।";
+        let newari = "सरकारं न्हूगु बजेट पिब्यूगु खः। थ्व बजेटय् बुँज्यामि पिनिगु लागि यक्व योजना दु। नगरपालिकां नं ज्या यानाच्वंगु दु।";
+
+        assert_eq!(language(nepali, &[]), Some(Language::Nepali));
+        assert_eq!(language(hindi, &[]), Some(Language::Hindi));
+        assert_eq!(language(maithili, &[]), Some(Language::Maithili));
+        assert_eq!(language(newari, &[]), Some(Language::Newari));
+    }
+
+    #[test]
+    fn the_script_beats_a_template_declaration() {
+        let nepali = "सरकारले नयाँ बजेट सार्वजनिक गरेको छ। यसमा किसानलाई पनि राहत दिइएको छ।";
+        assert_eq!(language(nepali, &[Some("en-US")]), Some(Language::Nepali));
+
+        let english = "The government presented a new budget on Friday, with more money for roads and farmers.";
+        assert_eq!(language(english, &[Some("ne")]), Some(Language::English));
+    }
+
+    #[test]
+    fn short_text_takes_the_declaration() {
+        assert_eq!(language("नमस्ते", &[None, Some("hi-IN")]), Some(Language::Hindi));
+        assert_eq!(language("नमस्ते", &[Some("np")]), Some(Language::Nepali));
+        assert_eq!(language("नमस्ते", &[Some("fr")]), None);
+        assert_eq!(language("", &[]), None);
+    }
+
+    #[test]
+    fn untelling_devanagari_is_nepali_unless_declared() {
+        let text = "काठमाडौं उपत्यका पोखरा विराटनगर भरतपुर बुटवल धनगढी जनकपुर";
+        assert_eq!(language(text, &[]), Some(Language::Nepali));
+        assert_eq!(language(text, &[Some("mai")]), Some(Language::Maithili));
+    }
+
+    #[test]
+    fn flags_mixed_script_pages() {
+        let mixed = "नेपाल क्रिकेट टिमले आज जित हासिल गरेको छ। Nepal beat the UAE by five wickets in Kirtipur today.";
+        assert!(identify(mixed, &[]).unwrap().mixed_script);
+
+        let nepali = "सरकारले नयाँ बजेट सार्वजनिक गरेको छ। यसमा किसानलाई पनि राहत दिइएको छ। PDF";
+        assert!(!identify(nepali, &[]).unwrap().mixed_script);
+    }
+}
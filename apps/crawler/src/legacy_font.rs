@@ -0,0 +1,268 @@
+/// Font families that share the Preeti keyboard layout.
+const LEGACY_FONTS: &[&str] = &["preeti", "kantipur"];
+
+/// Placeholder for `l` (ि), typed before the consonant it follows in Unicode.
+const I_SIGN_MARK: char = '\u{E000}';
+/// Placeholder for `{` (reph र्), typed after the syllable it precedes in Unicode.
+const REPH_MARK: char = '\u{E001}';
+/// Glyph combinations rewritten before the signs are moved, in this order.
+/// `\u{E002}` stands for `m`, a stroke that turns the glyph before it into
+/// another letter (`km` is फ) and is a visarga on its own.
+const SUBSTITUTIONS: &[(&str, &str)] = &[
+    ("त्र\u{E002}", "क्र"),
+    ("त्त\u{E002}", "क्त"),
+    ("प\u{E002}", "फ"),
+    ("भ\u{E002}", "झ"),
+    ("उ\u{E002}", "ऊ"),
+    ("\u{E002}", "ः"),
+    // A half form followed by the ा stroke is the full letter, as in `0f` for ण
+    ("्ा", ""),
+    // Nepali spelling has ण before retroflex stops where the half न is typed
+    ("न्ट", "ण्ट"),
+    ("न्ठ", "ण्ठ"),
+    ("न्ड", "ण्ड"),
+    ("न्ढ", "ण्ढ"),
+];
+
+/// Common Nepali words as they look when typed in Preeti (को, का, मा, ले, लाई,
+/// र, पनि, तथा, छ, छन्, हो, थियो, थिए, भयो, गर्न, गर्ने, गरेको, भएको, हुने,
+/// लागि, भने, तर, यो, नै), used to spot unmarked spans.
+const PREETI_WORDS: &[&str] = &[
+    "sf]", "sf", "df", "n]", "nfO{", "/", "klg", "tyf", "5", "5g\\", "xf]", "lyof]", "lyP", "eof]",
+    "ug{", "ug]{", "u/]sf]", "ePsf]", "x'g]", "nflu", "eg]", "t/", "of]", "g}",
+];
+
+/// Share of the words that must be known Preeti words.
+const MIN_KNOWN_SHARE: f64 = 0.2;
+
+/// Share of the words that must carry glyphs only Preeti text has.
+const MIN_MARKED_SHARE: f64 = 0.3;
+
+const ENGLISH_WORDS: &[&str] = &[
+    "the", "of", "and", "to", "in", "is", "for", "on", "with", "by", "at", "from", "this", "that", "are",
+];
+
+/// Converts text typed in the Preeti / Kantipur legacy fonts to Unicode Devanagari.
+///
+/// These fonts draw Devanagari glyphs over ASCII code points, so the page
+/// text itself is Latin garbage like `g]kfn` for नेपाल.
+pub struct PreetiConverter;
+
+impl PreetiConverter {
+    /// True if a CSS `font-family` value or `<font face>` names a Preeti-layout font.
+    pub fn is_legacy_font(font_family: &str) -> bool {
+        let family = font_family.to_lowercase();
+        LEGACY_FONTS.iter().any(|f| family.contains(f))
+    }
+
+    /// Heuristic for legacy text without any font hint: no Devanagari, hardly
+    /// any English, a good share of common Nepali words as typed in Preeti
+    /// and plenty of tokens that only make sense as Preeti. Short texts and
+    /// headlines seldom have enough of those and are left alone.
+    pub fn looks_like_preeti(text: &str) -> bool {
+        if text.chars().any(is_devanagari) {
+            return false;
+        }
+
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() < 4 {
+            return false;
+        }
+
+        let english = words.iter()
+            .filter(|w| ENGLISH_WORDS.contains(&w.to_lowercase().trim_matches(|c: char| !c.is_alphanumeric())))
+            .count();
+        // `.` is the danda in Preeti
+        let known = words.iter()
+            .filter(|w| PREETI_WORDS.contains(&w.trim_end_matches(['.', ','])))
+            .count();
+        let marked = words.iter().filter(|w| has_preeti_glyphs(w)).count();
+
+        let share = |count: usize| count as f64 / words.len() as f64;
+        english * 20 < words.len() && share(known) >= MIN_KNOWN_SHARE && share(marked) >= MIN_MARKED_SHARE
+    }
+
+    pub fn convert(text: &str) -> String {
+        let mut chars: Vec<char> = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            match map_char(c) {
+                Some(mapped) => chars.extend(mapped.chars()),
+                None => chars.push(c),
+            }
+        }
+
+        let chars = substitute(chars);
+        let chars = place_i_signs(chars);
+        let chars = place_rephs(chars);
+        compose_vowels(&chars.into_iter().collect::<String>())
+    }
+}
+
+fn map_char(c: char) -> Option<&'static str> {
+    let mapped = match c {
+        'a' => "ब", 'b' => "द", 'c' => "अ", 'd' => "म", 'e' => "भ", 'f' => "ा", 'g' => "न",
+        'h' => "ज", 'i' => "ष्", 'j' => "व", 'k' => "प", 'n' => "ल",
+        'o' => "य", 'p' => "उ", 'q' => "त्र", 'r' => "च", 's' => "क", 't' => "त", 'u' => "ग",
+        'v' => "ख", 'w' => "ध", 'x' => "ह", 'y' => "थ", 'z' => "श",
+        'A' => "ब्", 'B' => "द्य", 'C' => "ऋ", 'D' => "म्", 'E' => "भ्", 'F' => "ँ", 'G' => "न्",
+        'H' => "ज्", 'I' => "क्ष्", 'J' => "व्", 'K' => "प्", 'L' => "ी", 'M' => "ः", 'N' => "ल्",
+        'O' => "इ", 'P' => "ए", 'Q' => "त्त", 'R' => "च्", 'S' => "क्", 'T' => "त्", 'U' => "ग्",
+        'V' => "ख्", 'W' => "ध्", 'X' => "ह्", 'Y' => "थ्", 'Z' => "श्",
+        '`' => "ञ", '1' => "ज्ञ", '2' => "द्द", '3' => "घ", '4' => "द्ध", '5' => "छ", '6' => "ट",
+        '7' => "ठ", '8' => "ड", '9' => "ढ", '0' => "ण्", '-' => "(", '=' => ".",
+        '~' => "ञ्", '!' => "१", '@' => "२", '#' => "३", '$' => "४", '%' => "५", '^' => "६",
+        '&' => "७", '*' => "८", '(' => "९", ')' => "०", '_' => ")", '+' => "ं",
+        '[' => "ृ", ']' => "े", '\\' => "्", '}' => "ै", '|' => "्र",
+        ';' => "स", '\'' => "ु", ':' => "स्", '"' => "ू",
+        '.' => "।", '/' => "र", '<' => "?", '>' => "श्र", '?' => "रु",
+        'Í' => "ङ्क", 'Ë' => "ङ्ग", 'Î' => "ङ्ख", 'ª' => "ङ", '§' => "ट्ट", '¶' => "ठ्ठ",
+        'Ý' => "ट्ठ", '•' => "ड्ड", '‰' => "झ्", 'å' => "द्व", 'ß' => "द्म", 'Ì' => "न्न",
+        'Å' => "हृ", 'Œ' => "त्त्", 'Ø' => "्य", '¿' => "रू", '˜' => "ऽ", 'ç' => "ॐ",
+        '÷' => "/", 'Ö' => "=", 'Ù' => ";", 'Æ' => "“", 'Ú' => "’",
+        'l' => "\u{E000}", // I_SIGN_MARK
+        '{' => "\u{E001}", // REPH_MARK
+        'm' => "\u{E002}", // Stroke, see SUBSTITUTIONS
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+/// Applies [`SUBSTITUTIONS`].
+fn substitute(chars: Vec<char>) -> Vec<char> {
+    let text = SUBSTITUTIONS.iter()
+        .fold(chars.into_iter().collect::<String>(), |text, (from, to)| text.replace(from, to));
+    text.chars().collect()
+}
+
+/// Moves each ि from before its consonant cluster to after it.
+fn place_i_signs(chars: Vec<char>) -> Vec<char> {
+    let mut out = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != I_SIGN_MARK {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let end = cluster_end(&chars, i + 1);
+        out.extend_from_slice(&chars[i + 1..end]);
+        out.push('ि');
+        i = end.max(i + 1);
+    }
+    out
+}
+
+/// Moves each reph from after its syllable to before the syllable's consonant cluster.
+fn place_rephs(chars: Vec<char>) -> Vec<char> {
+    let mut out: Vec<char> = Vec::with_capacity(chars.len() + 4);
+    for c in chars {
+        if c != REPH_MARK {
+            out.push(c);
+            continue;
+        }
+
+        // इ + reph hook is how Preeti draws ई
+        if out.last() == Some(&'इ') {
+            out.pop();
+            out.push('ई');
+            continue;
+        }
+
+        let mut start = out.len();
+        while start > 0 && is_vowel_sign(out[start - 1]) {
+            start -= 1;
+        }
+        if start > 0 && is_consonant(out[start - 1]) {
+            start -= 1;
+            while start >= 2 && out[start - 1] == '्' && is_consonant(out[start - 2]) {
+                start -= 2;
+            }
+        }
+        out.splice(start..start, ['र', '्']);
+    }
+    out
+}
+
+/// End (exclusive) of the consonant cluster starting at `start`, e.g. प्र or क्ष.
+fn cluster_end(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() && is_consonant(chars[end]) {
+        end += 1;
+        if end + 1 < chars.len() && chars[end] == '्' && is_consonant(chars[end + 1]) {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+/// Preeti builds some vowels from two keys, e.g. `cf]` is अ + ा + े for ओ.
+fn compose_vowels(text: &str) -> String {
+    text.replace("ाे", "ो")
+        .replace("ाै", "ौ")
+        .replace("अा", "आ")
+        .replace("अो", "ओ")
+        .replace("अौ", "औ")
+        .replace("एे", "ऐ")
+        .replace("उः", "ऊ")
+}
+
+fn has_preeti_glyphs(word: &str) -> bool {
+    let has_letter = word.chars().any(|c| c.is_ascii_alphabetic());
+    let has_marker = word.chars().any(|c| matches!(c, ']' | '}' | '{' | '\\' | '|'));
+    has_letter && has_marker
+}
+
+fn is_devanagari(c: char) -> bool {
+    ('\u{0900}'..='\u{097F}').contains(&c)
+}
+
+fn is_consonant(c: char) -> bool {
+    ('\u{0915}'..='\u{0939}').contains(&c) || ('\u{0958}'..='\u{095F}').contains(&c)
+}
+
+fn is_vowel_sign(c: char) -> bool {
+    ('\u{093E}'..='\u{094C}').contains(&c) || matches!(c, 'ं' | 'ँ' | 'ः')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_known_words() {
+        for (preeti, unicode) in [
+            ("g]kfn", "नेपाल"),
+            ("sfo{qmd", "कार्यक्रम"),
+            ("lg0f{o", "निर्णय"),
+            ("kmf]g", "फोन"),
+            ("em'G8f", "झुण्डा"),
+            ("dGq]0fL", "मन्त्रेणी"),
+            ("ug]{", "गर्ने"),
+            ("k|wfgdGqL", "प्रधानमन्त्री"),
+            ("If]q", "क्षेत्र"),
+        ] {
+            assert_eq!(PreetiConverter::convert(preeti), unicode, "{}", preeti);
+        }
+    }
+
+    #[test]
+    fn spots_preeti_sentences() {
+        assert!(PreetiConverter::looks_like_preeti("g]kfn ;/sf/n] cfhb]lv gofF lgod nfu' ug]{ 5 ."));
+    }
+
+    #[test]
+    fn leaves_english_headlines_alone() {
+        for headline in [
+            "Galaxy S24 review: specs, price; verdict",
+            "Price list: item[1] costs Rs5; item[2] costs Rs10",
+            "C++ 23 [draft] adds std::print; compilers catch up",
+            "Nepal vs UAE: live score, 2nd T20 [updated]",
+            "Rs/kg prices of rice, dal and oil rise again",
+        ] {
+            assert!(!PreetiConverter::looks_like_preeti(headline), "{}", headline);
+        }
+    }
+}
@@ -1,8 +1,23 @@
+pub mod calendar;
+pub mod canonical;
+pub mod charset;
 pub mod config;
+pub mod dns;
 pub mod error;
+pub mod extractor;
+pub mod feed;
 pub mod fetcher;
+pub mod frontier;
+pub mod language;
+pub mod legacy_font;
+pub mod metadata;
 pub mod parser;
 pub mod spider;
 pub mod storage;
 pub mod stemmer;
 pub mod politeness;
+pub mod queue;
+pub mod readability;
+pub mod recrawl;
+pub mod retry;
+pub mod sitemap;
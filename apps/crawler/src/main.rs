@@ -40,7 +40,8 @@ async fn main() -> anyhow::Result<()> {
         spider_clone.shutdown();
     });
 
-    // Seed initial URLs (for testing MVP)
+    // Seed initial URLs (for testing MVP). Seeds already seen by a previous
+    // run are skipped and the persisted frontier is resumed instead.
     let seeds = vec![
         "https://www.nepal.gov.np".to_string(),
         "https://kathmandupost.com".to_string(),
@@ -75,7 +76,10 @@ async fn main() -> anyhow::Result<()> {
     // Run the spider with seeds
     match spider.run(seeds).await {
         Ok(_) => info!("Spider finished successfully."),
-        Err(e) => error!("Spider failed: {}", e),
+        Err(e) => {
+            error!("Spider failed: {}", e);
+            return Err(e.into());
+        }
     }
     
     Ok(())
@@ -0,0 +1,295 @@
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use crate::calendar::{self, BsDate, NEPAL_OFFSET_SECS};
+use scraper::{Html, Selector};
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+use crate::sitemap::parse_w3c_date;
+
+/// JSON-LD `@type`s describing the page as an article.
+const ARTICLE_TYPES: &[&str] = &[
+    "Article", "NewsArticle", "ReportageNewsArticle", "AnalysisNewsArticle", "OpinionNewsArticle",
+    "BlogPosting", "LiveBlogPosting", "Report",
+];
+
+/// JSON-LD `@type`s describing the site's publisher.
+const ORGANIZATION_TYPES: &[&str] = &[
+    "Organization", "NewsMediaOrganization", "Corporation", "GovernmentOrganization", "NGO",
+];
+
+/// `og:` properties of a page.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OpenGraph {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub url: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// `twitter:` card fields of a page.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TwitterCard {
+    pub card: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site: Option<String>,
+    pub creator: Option<String>,
+}
+
+/// A JSON-LD `NewsArticle` or other article type.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkedArticle {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub headline: Option<String>,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub date_published: Option<DateTime<Utc>>,
+    pub date_modified: Option<DateTime<Utc>>,
+    pub image: Option<String>,
+    pub publisher: Option<String>,
+}
+
+/// A JSON-LD `Organization`, either on its own or as an article's publisher.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkedOrganization {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub logo: Option<String>,
+}
+
+/// What a page says about itself in `<meta>` tags and JSON-LD, for result
+/// snippets and ranking. The fields at the bottom combine the sources, the
+/// most specific first.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PageMetadata {
+    /// `<meta name="description">`
+    pub description: Option<String>,
+    pub open_graph: OpenGraph,
+    pub twitter: TwitterCard,
+    pub article: Option<LinkedArticle>,
+    pub organization: Option<LinkedOrganization>,
+    pub authors: Vec<String>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub modified_at: Option<DateTime<Utc>>,
+    /// Preview image, absolute
+    pub image: Option<String>,
+}
+
+impl PageMetadata {
+    /// Description for a snippet, from whichever source has one.
+    pub fn summary(&self) -> Option<&str> {
+        self.description.as_deref()
+            .or(self.open_graph.description.as_deref())
+            .or(self.twitter.description.as_deref())
+            .or(self.article.as_ref().and_then(|a| a.description.as_deref()))
+    }
+}
+
+/// Reads the metadata of a parsed HTML document. URLs are resolved against `base`.
+pub fn extract(document: &Html, base: &Url) -> PageMetadata {
+    let mut metadata = PageMetadata::default();
+    let mut meta_author = None;
+    let mut article_authors = Vec::new();
+    let mut published_time = None;
+    let mut modified_time = None;
+
+    let meta_selector = Selector::parse("meta[content]").unwrap();
+    for el in document.select(&meta_selector) {
+        let value = el.value();
+        let Some(key) = value.attr("property").or(value.attr("name")) else {
+            continue;
+        };
+        let content = value.attr("content").unwrap_or_default().trim();
+        if content.is_empty() {
+            continue;
+        }
+        let content = Some(content.to_string());
+
+        match key.trim().to_lowercase().as_str() {
+            "description" => metadata.description = content,
+            "author" => meta_author = content,
+            "og:title" => metadata.open_graph.title = content,
+            "og:description" => metadata.open_graph.description = content,
+            "og:type" => metadata.open_graph.kind = content,
+            "og:url" => metadata.open_graph.url = content,
+            "og:image" | "og:image:url" | "og:image:secure_url" => {
+                metadata.open_graph.image = metadata.open_graph.image.or(content);
+            }
+            "og:site_name" => metadata.open_graph.site_name = content,
+            "og:locale" => metadata.open_graph.locale = content,
+            "twitter:card" => metadata.twitter.card = content,
+            "twitter:title" => metadata.twitter.title = content,
+            "twitter:description" => metadata.twitter.description = content,
+            "twitter:image" | "twitter:image:src" => metadata.twitter.image = content,
+            "twitter:site" => metadata.twitter.site = content,
+            "twitter:creator" => metadata.twitter.creator = content,
+            "article:author" => article_authors.extend(content),
+            "article:published_time" => published_time = content.as_deref().and_then(parse_date),
+            "article:modified_time" | "og:updated_time" => {
+                modified_time = modified_time.or(content.as_deref().and_then(parse_date));
+            }
+            _ => {}
+        }
+    }
+
+    let json_ld_selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    let mut publisher = None;
+    for script in document.select(&json_ld_selector) {
+        let json = script.text().collect::<String>();
+        // Some sites wrap it in a CDATA section or an HTML comment
+        let json = json.trim()
+            .trim_start_matches("//<![CDATA[").trim_end_matches("//]]>")
+            .trim_start_matches("<!--").trim_end_matches("-->");
+        let Ok(value) = serde_json::from_str::<Value>(json.trim()) else {
+            continue;
+        };
+        for object in json_ld_objects(&value) {
+            if metadata.article.is_none() && has_type(object, ARTICLE_TYPES) {
+                metadata.article = Some(linked_article(object));
+                // Articles usually name their publisher inline
+                publisher = object.get("publisher").map(linked_organization);
+            }
+            if metadata.organization.is_none() && has_type(object, ORGANIZATION_TYPES) {
+                metadata.organization = Some(linked_organization(object));
+            }
+        }
+    }
+    metadata.organization = metadata.organization.or(publisher);
+
+    let article = metadata.article.as_ref();
+    metadata.authors = article.map(|a| a.authors.clone()).filter(|a| !a.is_empty())
+        .or_else(|| meta_author.map(|author| vec![author]))
+        .unwrap_or_else(|| {
+            // article:author is often a profile URL rather than a name
+            article_authors.into_iter().filter(|a| !a.starts_with("http")).collect()
+        });
+    metadata.published_at = article.and_then(|a| a.date_published).or(published_time);
+    metadata.modified_at = article.and_then(|a| a.date_modified).or(modified_time);
+    metadata.image = metadata.open_graph.image.as_deref()
+        .or(metadata.twitter.image.as_deref())
+        .or(article.and_then(|a| a.image.as_deref()))
+        .and_then(|image| base.join(image).ok())
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(|url| url.to_string());
+
+    metadata
+}
+
+/// Every object in a JSON-LD block, whether it is one object, an array or an `@graph`.
+fn json_ld_objects(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().flat_map(json_ld_objects).collect(),
+        Value::Object(object) => {
+            let mut objects = vec![value];
+            if let Some(graph) = object.get("@graph") {
+                objects.extend(json_ld_objects(graph));
+            }
+            objects
+        }
+        _ => vec![],
+    }
+}
+
+fn has_type(object: &Value, types: &[&str]) -> bool {
+    match object.get("@type") {
+        Some(Value::String(kind)) => types.contains(&kind.as_str()),
+        Some(Value::Array(kinds)) => kinds.iter().any(|k| k.as_str().is_some_and(|k| types.contains(&k))),
+        _ => false,
+    }
+}
+
+fn linked_article(object: &Value) -> LinkedArticle {
+    let kind = match object.get("@type") {
+        Some(Value::Array(kinds)) => kinds.iter().find_map(Value::as_str),
+        other => other.and_then(Value::as_str),
+    };
+    LinkedArticle {
+        kind: kind.unwrap_or_default().to_string(),
+        headline: object.get("headline").or(object.get("name")).and_then(text),
+        description: object.get("description").and_then(text),
+        authors: object.get("author").map(names).unwrap_or_default(),
+        date_published: object.get("datePublished").and_then(text).as_deref().and_then(parse_date),
+        date_modified: object.get("dateModified").and_then(text).as_deref().and_then(parse_date),
+        image: object.get("image").and_then(url_of),
+        publisher: object.get("publisher").and_then(|p| p.get("name").and_then(text).or_else(|| text(p))),
+    }
+}
+
+fn linked_organization(object: &Value) -> LinkedOrganization {
+    LinkedOrganization {
+        name: object.get("name").and_then(text).or_else(|| text(object)),
+        url: object.get("url").and_then(url_of),
+        logo: object.get("logo").and_then(url_of),
+    }
+}
+
+/// A string value, or the `name` / `@value` of an object, or the first of an array.
+fn text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => Some(s.trim().to_string()),
+        Value::Object(object) => object.get("name").or(object.get("@value")).and_then(text),
+        Value::Array(items) => items.iter().find_map(text),
+        _ => None,
+    };
+    text.filter(|t| !t.is_empty())
+}
+
+/// Names of one or several people or organizations.
+fn names(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items.iter().filter_map(text).collect(),
+        other => text(other).into_iter().collect(),
+    }
+}
+
+/// A URL given as a string, an `ImageObject` or a list of either.
+fn url_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        Value::Object(object) => object.get("url").or(object.get("contentUrl")).and_then(url_of),
+        Value::Array(items) => items.iter().find_map(url_of),
+        _ => None,
+    }
+}
+
+/// Dates as pages write them: W3C / ISO 8601 with or without an offset,
+/// with a space instead of the `T`, or RFC 2822. Times without an offset are
+/// taken as Nepal time, as Nepali sites often leave it out. Some of them
+/// write Bikram Sambat dates even here; those become the start of the day.
+pub fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    let nepal = FixedOffset::east_opt(NEPAL_OFFSET_SECS)?;
+    let standard = parse_w3c_date(value)
+        .or_else(|| DateTime::parse_from_rfc2822(value).ok().map(|date| date.with_timezone(&Utc)))
+        .or_else(|| {
+            ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%:z"]
+                .iter()
+                .find_map(|format| DateTime::parse_from_str(value, format).ok())
+                .map(|date| date.with_timezone(&Utc))
+        });
+    if let Some(date) = standard {
+        // A year too far ahead is a Bikram Sambat one written the ISO way
+        let local = date.with_timezone(&nepal);
+        if !calendar::could_be_gregorian(local.year()) {
+            if let Some(date) = BsDate::new(local.year(), local.month(), local.day()).and_then(BsDate::to_ad) {
+                return calendar::nepal_midnight(date);
+            }
+        }
+        return Some(date);
+    }
+    if let Some(date) = BsDate::find_in(value).and_then(BsDate::to_ad) {
+        return calendar::nepal_midnight(date);
+    }
+
+    let naive = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y/%m/%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)))?;
+    naive.and_local_timezone(nepal).single().map(|date| date.with_timezone(&Utc))
+}
@@ -1,23 +1,157 @@
-use scraper::{Html, Selector};
+use ego_tree::NodeId;
+use scraper::{ElementRef, Html, Node, Selector};
 use url::Url;
+use crate::calendar::{self, BsDate};
 use crate::error::Result;
-use std::collections::HashSet;
+use crate::feed::FEED_TYPES;
+use crate::legacy_font::PreetiConverter;
+use crate::metadata::{self, PageMetadata};
+use crate::readability::{self, CODE_TAGS};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+/// `X-Robots-Tag` directives that carry a value after a colon, unlike a bot name prefix.
+const VALUED_DIRECTIVES: &[&str] = &["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+/// `X-Robots-Tag` directives without a value.
+const PLAIN_DIRECTIVES: &[&str] = &[
+    "all", "noindex", "nofollow", "none", "noarchive", "nocache", "nosnippet", "notranslate",
+    "noimageindex", "indexifembedded",
+];
+
+/// Directives that keep a page out of the index or its links out of the crawl.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RobotsDirectives {
+    pub noindex: bool,
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Directives of a `<meta name="robots">` content value, e.g. `noindex, nofollow`.
+    pub fn parse(content: &str) -> Self {
+        let mut directives = Self::default();
+        for directive in content.split(',') {
+            directives.apply(directive);
+        }
+        directives
+    }
+
+    /// Directives of one `X-Robots-Tag` header that apply to `bot_name`. A
+    /// directive may be prefixed with the bot it is meant for, as in
+    /// `otherbot: noindex, nofollow`; those for other bots are ignored. The
+    /// prefix reaches to the end of the header, so repeated headers must be
+    /// parsed one by one rather than joined.
+    pub fn from_header(value: &str, bot_name: &str) -> Self {
+        let mut directives = Self::default();
+        let mut applies = true;
+        let mut in_date = false;
+        for part in value.split(',') {
+            let mut directive = part.trim();
+            if let Some((prefix, rest)) = directive.split_once(':') {
+                let prefix = prefix.trim().to_lowercase();
+                if !VALUED_DIRECTIVES.contains(&prefix.as_str()) && is_bot_name(&prefix) {
+                    applies = prefix.eq_ignore_ascii_case(bot_name);
+                    directive = rest;
+                    in_date = false;
+                }
+            }
+
+            // The date of `unavailable_after` has commas of its own, as in
+            // `Wednesday, 03-Nov-2025 15:00:00 GMT`
+            let name = directive.split(':').next().unwrap_or_default().trim().to_lowercase();
+            let known = PLAIN_DIRECTIVES.contains(&name.as_str()) || VALUED_DIRECTIVES.contains(&name.as_str());
+            if in_date && !known {
+                continue;
+            }
+            in_date = name == "unavailable_after";
+
+            if applies {
+                directives.apply(directive);
+            }
+        }
+        directives
+    }
+
+    /// The stricter of both, as when a page and its headers both set directives.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+
+    fn apply(&mut self, directive: &str) {
+        match directive.trim().to_lowercase().as_str() {
+            "noindex" => self.noindex = true,
+            "nofollow" => self.nofollow = true,
+            "none" => {
+                self.noindex = true;
+                self.nofollow = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A user agent token as used in a directive prefix. Dates have spaces or
+/// are digits only before their first colon, so they never pass.
+fn is_bot_name(prefix: &str) -> bool {
+    !prefix.is_empty()
+        && !prefix.chars().all(|c| c.is_ascii_digit())
+        && prefix.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// How far into the main content a Bikram Sambat publication date is looked for.
+const PUBLISHED_DATE_SCAN_CHARS: usize = 500;
 
 #[derive(Debug)]
 pub struct ParsedPage {
     pub title: String,
     pub links: Vec<String>,
     pub text_content: String,
+    pub main_content: String, // The article without navigation, footers, ads and link lists
+    pub canonical_url: Option<String>, // From <link rel="canonical">, resolved against the page URL
+    pub feeds: Vec<String>, // RSS / Atom feeds advertised with <link rel="alternate">
+    pub robots: RobotsDirectives, // From <meta name="robots"> and the meta tag named after our bot
+    pub metadata: PageMetadata, // Description, OpenGraph, Twitter card, JSON-LD, authors and dates
+    pub lang: Option<String>, // From <html lang> or <meta http-equiv="content-language">
 }
 
 #[derive(Clone)]
 pub struct Parser {
     // Selectors can be pre-compiled here if needed
+    bot_name: Option<String>, // Honour <meta name="{bot_name}"> besides <meta name="robots">
+    content_selectors: HashMap<String, Selector>, // Main content of these domains, instead of guessing it
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parser {
     pub fn new() -> Self {
-        Self {}
+        Self { bot_name: None, content_selectors: HashMap::new() }
+    }
+
+    /// CSS selectors of the main content per domain, e.g. `ekantipur.com` → `.description`.
+    /// They apply to subdomains too. Invalid selectors are skipped with a warning.
+    pub fn with_content_selectors(mut self, selectors: &HashMap<String, String>) -> Self {
+        for (domain, selector) in selectors {
+            match Selector::parse(selector) {
+                Ok(parsed) => {
+                    self.content_selectors.insert(domain.trim_start_matches('.').to_lowercase(), parsed);
+                }
+                Err(e) => warn!("Invalid content selector for {}: {}", domain, e),
+            }
+        }
+        self
+    }
+
+    pub fn with_bot_name(mut self, bot_name: &str) -> Self {
+        self.bot_name = Some(bot_name.to_lowercase());
+        self
     }
 
     pub fn parse(&self, html_content: &str, base_url: &str) -> Result<ParsedPage> {
@@ -25,9 +159,12 @@ impl Parser {
         
         // Extract Title
         let title_selector = Selector::parse("title").unwrap();
-        let title = fragment.select(&title_selector).next()
+        let mut title = fragment.select(&title_selector).next()
             .map(|el| el.text().collect::<Vec<_>>().join(" "))
             .unwrap_or_default();
+        if PreetiConverter::looks_like_preeti(&title) {
+            title = PreetiConverter::convert(&title);
+        }
 
         // Extract Links
         let link_selector = Selector::parse("a[href]").unwrap();
@@ -35,6 +172,11 @@ impl Parser {
         
         let mut links = HashSet::new();
         for element in fragment.select(&link_selector) {
+            // The site asks for these not to be followed
+            let rel = element.value().attr("rel").unwrap_or_default();
+            if rel.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case("nofollow")) {
+                continue;
+            }
             if let Some(href) = element.value().attr("href") {
                 if let Ok(url) = base.join(href) {
                      // Basic filter: Only http/https
@@ -45,19 +187,214 @@ impl Parser {
             }
         }
 
-        // Extract Text Content (naive approach for now)
+        // Extract Canonical URL
+        let canonical_selector = Selector::parse(r#"link[rel~="canonical"][href]"#).unwrap();
+        let canonical_url = fragment.select(&canonical_selector).next()
+            .and_then(|el| el.value().attr("href"))
+            .and_then(|href| base.join(href.trim()).ok())
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+            .map(|url| url.to_string());
+
+        // Extract Feed URLs
+        let feed_selector = Selector::parse(r#"link[rel~="alternate"][type][href]"#).unwrap();
+        let feeds = fragment.select(&feed_selector)
+            .filter(|el| {
+                let kind = el.value().attr("type").unwrap_or_default().trim().to_lowercase();
+                FEED_TYPES.contains(&kind.as_str())
+            })
+            .filter_map(|el| el.value().attr("href"))
+            .filter_map(|href| base.join(href.trim()).ok())
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+            .map(|url| url.to_string())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        // Extract Robots Directives
+        let meta_selector = Selector::parse("meta[name][content]").unwrap();
+        let robots = fragment.select(&meta_selector)
+            .filter(|el| {
+                let name = el.value().attr("name").unwrap_or_default().trim().to_lowercase();
+                name == "robots" || self.bot_name.as_deref() == Some(name.as_str())
+            })
+            .map(|el| RobotsDirectives::parse(el.value().attr("content").unwrap_or_default()))
+            .fold(RobotsDirectives::default(), RobotsDirectives::merge);
+
+        // Extract Declared Language
+        let html_selector = Selector::parse("html").unwrap();
+        let content_language_selector = Selector::parse("meta[http-equiv][content]").unwrap();
+        let lang = fragment.select(&html_selector).next()
+            .and_then(|el| el.value().attr("lang").or(el.value().attr("xml:lang")))
+            .or_else(|| {
+                fragment.select(&content_language_selector)
+                    .find(|el| el.value().attr("http-equiv").unwrap_or_default().trim().eq_ignore_ascii_case("content-language"))
+                    .and_then(|el| el.value().attr("content"))
+            })
+            .map(str::trim)
+            .filter(|lang| !lang.is_empty())
+            .map(str::to_string);
+
+        // Extract Structured Metadata
+        let mut metadata = metadata::extract(&fragment, &base);
+
+        // Extract Text Content, converting legacy-font spans to Unicode
         let body_selector = Selector::parse("body").unwrap();
-        let text_content = fragment.select(&body_selector).next()
-             .map(|el| el.text().collect::<Vec<_>>().join(" "))
-             .unwrap_or_default()
-             .split_whitespace()
-             .collect::<Vec<_>>()
-             .join(" ");
+        let legacy_classes = legacy_font_classes(&fragment);
+        let body = fragment.select(&body_selector).next();
+        let text_content = body
+             .map(|body| collapse_whitespace(&extract_text(body, &legacy_classes, &HashSet::new())))
+             .unwrap_or_default();
+
+        // Extract Main Content
+        let main_content = body
+             .map(|body| collapse_whitespace(&self.main_text(body, base.host_str(), &legacy_classes)))
+             .unwrap_or_default();
+
+        // Nepali news pages print their date by the headline, in Bikram Sambat
+        if metadata.published_at.is_none() {
+            let head: String = main_content.chars().take(PUBLISHED_DATE_SCAN_CHARS).collect();
+            metadata.published_at = BsDate::find_in(&head)
+                .and_then(BsDate::to_ad)
+                .and_then(calendar::nepal_midnight);
+        }
 
         Ok(ParsedPage {
             title,
             links: links.into_iter().collect(),
             text_content,
+            main_content,
+            canonical_url,
+            feeds,
+            robots,
+            metadata,
+            lang,
         })
     }
 }
+
+impl Parser {
+    /// Text of the main content: the site's own selector if one is configured
+    /// and matches, else the block readability picks, else the body without
+    /// navigation, headers, footers and asides.
+    fn main_text(&self, body: ElementRef, host: Option<&str>, legacy_classes: &HashSet<String>) -> String {
+        if let Some(selector) = host.and_then(|host| self.content_selector_for(host)) {
+            let parts: Vec<String> = body.select(selector)
+                .map(|el| extract_text(el, legacy_classes, &HashSet::new()))
+                .collect();
+            if !parts.is_empty() {
+                return parts.join(" ");
+            }
+        }
+
+        match readability::main_content(body) {
+            Some(main) => extract_text(main.root, legacy_classes, &main.excluded),
+            None => extract_text(body, legacy_classes, &readability::boilerplate(body)),
+        }
+    }
+
+    fn content_selector_for(&self, host: &str) -> Option<&Selector> {
+        // Most specific match wins, as with canonicalization rules
+        let mut domain = host;
+        loop {
+            if let Some(selector) = self.content_selectors.get(domain) {
+                return Some(selector);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Text of `root`, with spans set in a legacy Nepali font converted to Unicode.
+/// Spans are recognized by their font (inline style, `<font face>` or a class
+/// styled in a `<style>` block), or by the text itself when nothing marks them.
+/// Scripts and styles are left out, as is everything below an `excluded` element.
+fn extract_text(root: ElementRef, legacy_classes: &HashSet<String>, excluded: &HashSet<NodeId>) -> String {
+    let mut parts = Vec::new();
+    for node in root.descendants() {
+        let Node::Text(text) = node.value() else {
+            continue;
+        };
+
+        let mut skipped = false;
+        let mut legacy_font = false;
+        for ancestor in node.ancestors().filter_map(ElementRef::wrap) {
+            let el = ancestor.value();
+            if CODE_TAGS.contains(&el.name()) || excluded.contains(&ancestor.id()) {
+                skipped = true;
+                break;
+            }
+            let styled = el.attr("style").is_some_and(|style| {
+                style.to_lowercase().contains("font-family") && PreetiConverter::is_legacy_font(style)
+            });
+            let face = el.name() == "font" && el.attr("face").is_some_and(PreetiConverter::is_legacy_font);
+            let class = el.classes().any(|c| legacy_classes.contains(c));
+            legacy_font |= styled || face || class;
+        }
+
+        if skipped {
+            continue;
+        }
+        if legacy_font || PreetiConverter::looks_like_preeti(text) {
+            parts.push(PreetiConverter::convert(text));
+        } else {
+            parts.push(text.to_string());
+        }
+    }
+    parts.join(" ")
+}
+
+/// Class names that `<style>` blocks render in a legacy Nepali font.
+fn legacy_font_classes(document: &Html) -> HashSet<String> {
+    let style_selector = Selector::parse("style").unwrap();
+    let mut classes = HashSet::new();
+
+    for style in document.select(&style_selector) {
+        let css = style.text().collect::<String>();
+        for rule in css.split('}') {
+            let Some((selectors, declarations)) = rule.split_once('{') else {
+                continue;
+            };
+            if !declarations.to_lowercase().contains("font-family") || !PreetiConverter::is_legacy_font(declarations) {
+                continue;
+            }
+            for selector in selectors.split(',') {
+                // Only the class of the last compound selector, e.g. `div .preeti`
+                let last = selector.split_whitespace().last().unwrap_or_default();
+                for class in last.split('.').skip(1) {
+                    let class = class.split([':', '[', '#']).next().unwrap_or_default();
+                    if !class.is_empty() {
+                        classes.insert(class.to_string());
+                    }
+                }
+            }
+        }
+    }
+    classes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_directives_for_other_bots_are_ignored() {
+        let directives = RobotsDirectives::from_header("otherbot: noindex, nofollow", "ourbot");
+        assert_eq!(directives, RobotsDirectives::default());
+        let directives = RobotsDirectives::from_header("ourbot: noindex", "OurBot");
+        assert!(directives.noindex);
+    }
+
+    #[test]
+    fn unavailable_after_dates_are_not_bot_names() {
+        let directives = RobotsDirectives::from_header("unavailable_after: Wednesday, 03-Nov-2025 15:00:00 GMT, noindex", "ourbot");
+        assert_eq!(directives, RobotsDirectives { noindex: true, nofollow: false });
+        let directives = RobotsDirectives::from_header("unavailable_after: 2025-11-03T15:00:00Z, nofollow", "ourbot");
+        assert_eq!(directives, RobotsDirectives { noindex: false, nofollow: true });
+        let directives = RobotsDirectives::from_header("unavailable_after: Wednesday, 03-Nov-2025 15:00:00 GMT, otherbot: noindex", "ourbot");
+        assert_eq!(directives, RobotsDirectives::default());
+    }
+}
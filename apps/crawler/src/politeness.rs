@@ -1,104 +1,534 @@
-use crate::fetcher::Fetcher;
+use crate::canonical::registrable_domain;
+use crate::charset;
+use crate::config::AppConfig;
+use crate::dns::DnsCache;
+use crate::error::{CrawlerError, Result};
+use crate::fetcher::{FetchResult, Fetcher};
 use texting_robots::Robot;
 use dashmap::DashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use key_lock::KeyLock;
 use url::Url;
 use tracing::debug;
 use governor::{Quota, RateLimiter, state::NotKeyed, state::InMemoryState, clock::DefaultClock};
-use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+use governor::clock::Clock;
+use reqwest::StatusCode;
+use crate::frontier::{host_of, now_millis};
+use fred::prelude::*;
+use serde::{Deserialize, Serialize};
 
+/// RFC 9309 asks crawlers to parse at least this much of a robots.txt.
+const MAX_ROBOTS_BYTES: usize = 500 * 1024;
+
+/// Whether robots.txt lets us fetch a URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RobotsAccess {
+    Allowed,
+    Disallowed,
+    /// The host's robots.txt could not be fetched (5xx, timeout); try again after this
+    Unreachable(Duration),
+}
+
+/// A robots.txt as fetched, which is what instances share through Redis.
+/// Each of them parses it for itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RobotsRecord {
+    /// HTTP status, 0 when the request itself failed
+    status: u16,
+    body: String,
+    /// Unix ms after which the record is fetched again
+    expires_at: i64,
+}
+
+impl RobotsRecord {
+    fn unreachable(&self) -> bool {
+        self.status == 0 || StatusCode::from_u16(self.status).is_ok_and(|s| s.is_server_error())
+    }
+}
+
+#[derive(Clone)]
+enum Rules {
+    AllowAll,
+    DisallowAll,
+    Parsed(Arc<Robot>),
+}
+
+/// Counters of where robots.txt lookups that missed the memory cache were answered.
+#[derive(Debug, Default)]
+pub struct RobotsMetrics {
+    /// robots.txt requests actually sent
+    pub fetches: AtomicU64,
+    /// Lookups that waited for another caller's fetch instead of sending their own
+    pub coalesced: AtomicU64,
+    /// Lookups answered by another instance's copy in Redis
+    pub shared_hits: AtomicU64,
+}
+
+#[derive(Clone)]
+struct CachedRobots {
+    record: RobotsRecord,
+    rules: Rules,
+}
+
+/// Fetches, caches and evaluates robots.txt following RFC 9309.
+///
+/// A 2xx robots.txt is obeyed, 4xx means there are no rules, and a 5xx or a
+/// failed request disallows the whole host until it is retried after the
+/// error TTL (a previously fetched copy keeps being used meanwhile). Records
+/// are cached in memory and in Redis under `robots:{origin}`, so a fleet of
+/// crawlers fetches each robots.txt once per TTL. Within one instance the
+/// lookups of an origin are single-flight: the first caller fetches and
+/// everyone arriving meanwhile waits for its result.
 pub struct RobotsManager {
     fetcher: Fetcher,
-    cache: DashMap<String, Arc<Robot>>,
+    redis: Client,
+    cache: DashMap<String, CachedRobots>,
+    in_flight: KeyLock<String>,
+    metrics: RobotsMetrics,
     user_agent: String,
+    ttl: Duration,
+    error_ttl: Duration,
 }
 
 impl RobotsManager {
-    pub fn new(fetcher: Fetcher, user_agent: String) -> Self {
+    pub fn new(fetcher: Fetcher, redis: Client, user_agent: String, ttl: Duration, error_ttl: Duration) -> Self {
         Self {
             fetcher,
+            redis,
             cache: DashMap::new(),
+            in_flight: KeyLock::new(),
+            metrics: RobotsMetrics::default(),
             user_agent,
+            ttl,
+            error_ttl,
         }
     }
 
+    pub fn metrics(&self) -> &RobotsMetrics {
+        &self.metrics
+    }
+
     pub async fn can_fetch(&self, url_str: &str) -> bool {
-        let url = match Url::parse(url_str) {
-            Ok(u) => u,
-            Err(_) => return false,
+        self.access(url_str).await == RobotsAccess::Allowed
+    }
+
+    pub async fn access(&self, url_str: &str) -> RobotsAccess {
+        let Ok(url) = Url::parse(url_str) else {
+            return RobotsAccess::Disallowed;
         };
+        let Some(robots) = self.robots_for(&url).await else {
+            return RobotsAccess::Disallowed;
+        };
+
+        match robots.rules {
+            Rules::AllowAll => RobotsAccess::Allowed,
+            Rules::DisallowAll if robots.record.unreachable() => {
+                let retry_in = (robots.record.expires_at - now_millis()).max(0) as u64;
+                RobotsAccess::Unreachable(Duration::from_millis(retry_in))
+            }
+            Rules::DisallowAll => RobotsAccess::Disallowed,
+            // Rules may match on the query string too, so the whole URL is checked
+            Rules::Parsed(robot) if robot.allowed(url.as_str()) => RobotsAccess::Allowed,
+            Rules::Parsed(_) => RobotsAccess::Disallowed,
+        }
+    }
+
+    /// `Sitemap:` URLs listed in the robots.txt of the URL's host.
+    pub async fn sitemaps(&self, url_str: &str) -> Vec<String> {
+        match self.robot_for(url_str).await {
+            Some(robot) => robot.sitemaps.clone(),
+            None => vec![],
+        }
+    }
+
+    /// `Crawl-delay` the host's robots.txt sets for our user agent, at most [`MAX_CRAWL_DELAY`].
+    pub async fn crawl_delay(&self, url_str: &str) -> Option<Duration> {
+        let delay = self.robot_for(url_str).await?.delay?;
+        // Clamped before the conversion, which panics on values like 1e20
+        (delay.is_finite() && delay > 0.0)
+            .then(|| Duration::from_secs_f32(delay.min(MAX_CRAWL_DELAY.as_secs_f32())))
+    }
+
+    async fn robot_for(&self, url_str: &str) -> Option<Arc<Robot>> {
+        let url = Url::parse(url_str).ok()?;
+        match self.robots_for(&url).await?.rules {
+            Rules::Parsed(robot) => Some(robot),
+            _ => None,
+        }
+    }
 
-        let domain = match url.host_str() {
-            Some(d) => d,
-            None => return false,
+    /// Robots.txt of the URL's origin: from memory, from Redis, or fetched.
+    /// `None` if the URL has no host.
+    async fn robots_for(&self, url: &Url) -> Option<CachedRobots> {
+        let origin = url.origin();
+        if !origin.is_tuple() {
+            return None;
+        }
+        let origin = origin.ascii_serialization();
+
+        if let Some(cached) = self.fresh(&origin) {
+            return Some(cached);
+        }
+
+        let _flight = self.in_flight.lock(origin.clone()).await;
+        // Whoever held the lock before us may have fetched it already
+        if let Some(cached) = self.fresh(&origin) {
+            self.metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+            return Some(cached);
+        }
+        let stale = self.cache.get(&origin).map(|cached| cached.record.clone());
+
+        let record = match self.load_shared(&origin).await {
+            Some(record) => {
+                self.metrics.shared_hits.fetch_add(1, Ordering::Relaxed);
+                record
+            }
+            None => {
+                self.metrics.fetches.fetch_add(1, Ordering::Relaxed);
+                let record = self.fetch(&origin, stale).await;
+                self.store_shared(&origin, &record).await;
+                record
+            }
         };
 
-        let robot = if let Some(r) = self.cache.get(domain) {
-            r.clone()
-        } else {
-            let robots_url = format!("{}://{}/robots.txt", url.scheme(), domain);
-            match self.fetcher.fetch(&robots_url).await {
-                Ok((_, body)) => {
-                    match Robot::new(&self.user_agent, body.as_bytes()) {
-                        Ok(r) => {
-                            let arc_r = Arc::new(r);
-                            self.cache.insert(domain.to_string(), arc_r.clone());
-                            arc_r
-                        }
-                        Err(e) => {
-                            debug!("Failed to parse robots.txt for {}: {}", domain, e);
-                            return true;
-                        }
-                    }
-                }
-                Err(e) => {
-                    debug!("No robots.txt found for {}: {}", domain, e);
-                    // Default allowance if robots.txt is missing
-                    return true;
-                }
+        let cached = CachedRobots { rules: self.rules_of(&record), record };
+        self.cache.insert(origin, cached.clone());
+        Some(cached)
+    }
+
+    fn fresh(&self, origin: &str) -> Option<CachedRobots> {
+        self.cache.get(origin)
+            .filter(|cached| cached.record.expires_at > now_millis())
+            .map(|cached| cached.clone())
+    }
+
+    async fn fetch(&self, origin: &str, stale: Option<RobotsRecord>) -> RobotsRecord {
+        let robots_url = format!("{}/robots.txt", origin);
+        let (status, mut body) = match self.fetcher.fetch(&robots_url).await {
+            Ok(fetched) => {
+                let (body, _) = charset::decode(&fetched.body, fetched.content_type.as_deref(), &robots_url);
+                (fetched.status.as_u16(), body)
+            }
+            Err(e) => {
+                debug!("Failed to fetch {}: {}", robots_url, e);
+                (0, String::new())
             }
         };
 
-        // texting_robots::Robot::allowed(path)
-        robot.allowed(url.path())
+        let fetched = RobotsRecord { status, body: String::new(), expires_at: 0 };
+        if fetched.unreachable() {
+            debug!("robots.txt unreachable for {} ({}), retrying in {:?}", origin, status, self.error_ttl);
+            let expires_at = now_millis() + self.error_ttl.as_millis() as i64;
+            // An earlier copy beats disallowing everything until the host recovers
+            return match stale.filter(|s| !s.unreachable()) {
+                Some(stale) => RobotsRecord { expires_at, ..stale },
+                None => RobotsRecord { expires_at, ..fetched },
+            };
+        }
+
+        if body.len() > MAX_ROBOTS_BYTES {
+            let mut end = MAX_ROBOTS_BYTES;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+        }
+        RobotsRecord { status, body, expires_at: now_millis() + self.ttl.as_millis() as i64 }
     }
+
+    fn rules_of(&self, record: &RobotsRecord) -> Rules {
+        if record.unreachable() {
+            return Rules::DisallowAll;
+        }
+        if !(200..300).contains(&record.status) {
+            // 4xx (and anything else that is not an error on their side): no rules apply
+            return Rules::AllowAll;
+        }
+        match Robot::new(&self.user_agent, record.body.as_bytes()) {
+            Ok(robot) => Rules::Parsed(Arc::new(robot)),
+            Err(e) => {
+                debug!("Failed to parse robots.txt: {}", e);
+                Rules::AllowAll
+            }
+        }
+    }
+
+    async fn load_shared(&self, origin: &str) -> Option<RobotsRecord> {
+        let stored: Option<String> = match self.redis.get(robots_key(origin)).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                debug!("Could not read shared robots.txt for {}: {}", origin, e);
+                return None;
+            }
+        };
+        stored
+            .and_then(|json| serde_json::from_str::<RobotsRecord>(&json).ok())
+            .filter(|record| record.expires_at > now_millis())
+    }
+
+    async fn store_shared(&self, origin: &str, record: &RobotsRecord) {
+        let secs = ((record.expires_at - now_millis()) / 1000).max(1);
+        let Ok(json) = serde_json::to_string(record) else {
+            return;
+        };
+        let stored = self.redis
+            .set::<(), _, _>(robots_key(origin), json, Some(Expiration::EX(secs)), None, false)
+            .await;
+        if let Err(e) = stored {
+            debug!("Could not share robots.txt for {}: {}", origin, e);
+        }
+    }
+}
+
+fn robots_key(origin: &str) -> String {
+    format!("robots:{}", origin)
+}
+
+/// How fast one host is crawled right now.
+///
+/// The pace starts from the slower of the configured rate and the host's
+/// `Crawl-delay`, and then follows how the host copes: it backs off on
+/// 429/503 answers, connection errors and rising latency, and creeps back
+/// towards the starting pace after a run of healthy responses.
+struct HostPace {
+    /// Delay we may never go below
+    base_delay: Duration,
+    delay: Duration,
+    limiter: Arc<DirectLimiter>,
+    /// Set from `Retry-After`, nothing is sent before then
+    blocked_until: Option<Instant>,
+    /// Moving average of response times, and the best it has been
+    latency: Option<Duration>,
+    best_latency: Option<Duration>,
+    healthy_streak: u32,
+}
+
+type DirectLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+impl HostPace {
+    fn new(base_delay: Duration) -> Self {
+        Self {
+            base_delay,
+            delay: base_delay,
+            limiter: Arc::new(limiter_for(base_delay)),
+            blocked_until: None,
+            latency: None,
+            best_latency: None,
+            healthy_streak: 0,
+        }
+    }
+
+    fn set_delay(&mut self, delay: Duration) {
+        let delay = delay.clamp(self.base_delay, MAX_DELAY.max(self.base_delay));
+        if delay != self.delay {
+            self.delay = delay;
+            self.limiter = Arc::new(limiter_for(delay));
+        }
+    }
+
+    fn slow_down(&mut self) {
+        self.healthy_streak = 0;
+        self.set_delay(self.delay * 2);
+    }
+}
+
+fn limiter_for(delay: Duration) -> DirectLimiter {
+    let quota = Quota::with_period(delay.max(Duration::from_millis(1))).unwrap();
+    RateLimiter::direct(quota)
+}
+
+/// What hosts share a rate limit on top of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolitenessGroup {
+    /// Every host is limited on its own only
+    Host,
+    /// Hosts resolving to the same IP address, e.g. sites on one shared hosting box
+    Ip,
+    /// Hosts under the same registrable domain, e.g. all of `*.gov.np` ministries stay apart
+    /// but `news.example.com.np` and `www.example.com.np` share
+    Domain,
 }
 
+/// Slowest pace adaptive backoff goes to, unless Crawl-delay asks for more.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// Crawl-delay values beyond this are taken as this.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(120);
+/// Healthy responses in a row before the pace speeds up a step.
+const HEALTHY_STREAK: u32 = 10;
+/// Responses this much slower than the host's best average count as strain.
+const LATENCY_STRAIN_FACTOR: u32 = 3;
+
 pub struct PolitenessManager {
     robots: RobotsManager,
-    limiters: DashMap<String, Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>>,
-    rate_per_sec: u32,
+    dns: DnsCache,
+    hosts: DashMap<String, HostPace>,
+    default_delay: Duration,
+    group: PolitenessGroup,
+    groups: DashMap<String, Arc<DirectLimiter>>,
+    group_delay: Duration,
 }
 
 impl PolitenessManager {
-    pub fn new(fetcher: Fetcher, user_agent: String, rate_per_sec: u32) -> Self {
+    pub fn new(robots: RobotsManager, dns: DnsCache, config: &AppConfig) -> Self {
         Self {
-            robots: RobotsManager::new(fetcher, user_agent),
-            limiters: DashMap::new(),
-            rate_per_sec,
+            robots,
+            dns,
+            hosts: DashMap::new(),
+            default_delay: Duration::from_secs(1) / config.rate_limit_per_domain.max(1),
+            group: config.politeness_group,
+            groups: DashMap::new(),
+            group_delay: Duration::from_secs(1) / config.group_rate_limit.max(1),
         }
     }
 
-    pub async fn check_and_wait(&self, url_str: &str) -> bool {
-        if !self.robots.can_fetch(url_str).await {
-            return false;
+    /// Robots.txt check for a URL that the scheduler already cleared for rate limiting.
+    /// Also picks up the host's `Crawl-delay` once its robots.txt is known.
+    pub async fn access(&self, url_str: &str) -> RobotsAccess {
+        let access = self.robots.access(url_str).await;
+        if let (Some(host), Some(delay)) = (host_of(url_str), self.robots.crawl_delay(url_str).await) {
+            self.apply_crawl_delay(&host, delay);
+        }
+        access
+    }
+
+    pub async fn is_allowed(&self, url_str: &str) -> bool {
+        self.access(url_str).await == RobotsAccess::Allowed
+    }
+
+    pub async fn sitemaps(&self, url_str: &str) -> Vec<String> {
+        self.robots.sitemaps(url_str).await
+    }
+
+    pub fn robots_metrics(&self) -> &RobotsMetrics {
+        self.robots.metrics()
+    }
+
+    /// Takes one request slot from the host's rate limiter, and from its
+    /// group's, without waiting. When either is empty, returns how long until
+    /// it has a slot again.
+    pub async fn try_acquire(&self, host: &str) -> std::result::Result<(), Duration> {
+        // The host goes first: it is the cheap check, and if the group is full
+        // only this host loses its slot
+        self.try_acquire_host(host)?;
+        if let Some(group) = self.group_of(host) {
+            let limiter = self.groups.entry(group)
+                .or_insert_with(|| Arc::new(limiter_for(self.group_delay)))
+                .clone();
+            limiter.check().map_err(|not_until| not_until.wait_time_from(DefaultClock::default().now()))?;
         }
+        Ok(())
+    }
 
-        let url = match Url::parse(url_str) {
-            Ok(u) => u,
-            Err(_) => return false,
+    fn try_acquire_host(&self, host: &str) -> std::result::Result<(), Duration> {
+        let limiter = {
+            let pace = self.pace(host);
+            if let Some(until) = pace.blocked_until {
+                let now = Instant::now();
+                if until > now {
+                    return Err(until - now);
+                }
+            }
+            pace.limiter.clone()
         };
 
-        let domain = match url.host_str() {
-            Some(d) => d,
-            None => return false,
+        limiter.check().map_err(|not_until| not_until.wait_time_from(DefaultClock::default().now()))
+    }
+
+    /// Waits until the host's rate limiter has a slot, for fetches that run
+    /// outside the scheduler such as sitemaps and feeds.
+    pub async fn acquire(&self, host: &str) {
+        while let Err(wait) = self.try_acquire(host).await {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Adapts the host's pace to the outcome of a fetch from it.
+    pub fn record_fetch(&self, host: &str, fetched: &Result<FetchResult>) {
+        match fetched {
+            Ok(fetched) => self.record_response(host, fetched.status, fetched.ttfb, fetched.retry_after),
+            Err(CrawlerError::Network(e)) if e.is_connect() || e.is_timeout() => self.record_error(host),
+            Err(_) => {}
+        }
+    }
+
+    fn record_response(&self, host: &str, status: StatusCode, latency: Duration, retry_after: Option<Duration>) {
+        let mut pace = self.pace(host);
+
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            pace.slow_down();
+            let wait = retry_after.unwrap_or(pace.delay).min(MAX_DELAY * 10);
+            pace.blocked_until = Some(Instant::now() + wait);
+            debug!("{} answered {}, backing off to one request per {:?}", host, status, pace.delay);
+            return;
+        }
+
+        let average = match pace.latency {
+            Some(average) => average.mul_f64(0.8) + latency.mul_f64(0.2),
+            None => latency,
         };
+        pace.latency = Some(average);
+        let best = pace.best_latency.map_or(average, |best| best.min(average));
+        pace.best_latency = Some(best);
+
+        if average > best * LATENCY_STRAIN_FACTOR && average > Duration::from_millis(500) {
+            debug!("{} responds slower ({:?} vs {:?}), backing off", host, average, best);
+            pace.slow_down();
+            // Start over from the new level so one slow phase only counts once
+            pace.best_latency = Some(average);
+            return;
+        }
+
+        pace.healthy_streak += 1;
+        if pace.healthy_streak >= HEALTHY_STREAK && pace.delay > pace.base_delay {
+            pace.healthy_streak = 0;
+            let faster = pace.delay.mul_f64(0.75);
+            pace.set_delay(faster);
+        }
+    }
+
+    /// Backs off from a host whose connection failed or timed out.
+    fn record_error(&self, host: &str) {
+        self.pace(host).slow_down();
+    }
 
-        let limiter = self.limiters.entry(domain.to_string()).or_insert_with(|| {
-            Arc::new(RateLimiter::direct(Quota::per_second(NonZeroU32::new(self.rate_per_sec).unwrap())))
-        }).clone();
+    fn apply_crawl_delay(&self, host: &str, crawl_delay: Duration) {
+        let mut pace = self.pace(host);
+        let base = crawl_delay.max(self.default_delay);
+        if pace.base_delay != base {
+            debug!("{} asks for Crawl-delay {:?}", host, crawl_delay);
+            // Keep backing off if we already are slower than the new floor
+            let delay = pace.delay.max(base);
+            pace.base_delay = base;
+            pace.delay = delay;
+            pace.limiter = Arc::new(limiter_for(delay));
+        }
+    }
+
+    /// Key of the shared limit `host` falls under, if grouping is on and the host resolves.
+    /// Never waits for DNS: a host not in the cache yet is resolved in the
+    /// background and goes ungrouped until then.
+    fn group_of(&self, host: &str) -> Option<String> {
+        match self.group {
+            PolitenessGroup::Host => None,
+            PolitenessGroup::Ip => match self.dns.cached(host) {
+                Some(Ok(ips)) => ips.first().map(|ip| format!("ip:{}", ip)),
+                Some(Err(e)) => {
+                    debug!("Could not resolve {}: {}", host, e);
+                    None
+                }
+                None => {
+                    self.dns.prefetch(host);
+                    None
+                }
+            },
+            PolitenessGroup::Domain => Some(format!("domain:{}", registrable_domain(host))),
+        }
+    }
 
-        limiter.until_ready().await;
-        true
+    fn pace(&self, host: &str) -> dashmap::mapref::one::RefMut<'_, String, HostPace> {
+        self.hosts.entry(host.to_string()).or_insert_with(|| HostPace::new(self.default_delay))
     }
 }
@@ -0,0 +1,272 @@
+use crate::config::AppConfig;
+use crate::error::{CrawlerError, Result};
+use crate::frontier::Priority;
+use dashmap::DashMap;
+use futures::StreamExt;
+use lapin::acker::Acker;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+    ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use lapin::publisher_confirm::Confirmation;
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Channel, ChannelState, Connection, ConnectionProperties, ExchangeKind};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use url::Url;
+
+const EXCHANGE: &str = "crawl.links";
+
+/// Highest AMQP message priority we use, one level per `Priority` variant.
+const MAX_PRIORITY: u8 = Priority::Low as u8;
+
+/// Deliveries received from our shards, grouped by host so the spider can
+/// schedule them the same way it schedules the Redis frontier.
+#[derive(Default)]
+struct LocalQueues {
+    queues: HashMap<String, VecDeque<(String, u64, Acker)>>,
+    ready_at: HashMap<String, Instant>,
+}
+
+/// RabbitMQ transport for distributed crawls.
+///
+/// Discovered links are published to the `crawl.links` exchange and routed
+/// by host to one of `shard_count` durable queues, so every host is always
+/// crawled by the same worker and its politeness limits stay correct. Each
+/// worker consumes the shards it owns and only acks a delivery once the
+/// spider acks its lease, i.e. after the document was stored.
+///
+/// A consumer that fails is not resubscribed: its unacked deliveries go back
+/// to the queue with the connection, so the broker reports the failure
+/// through [`Broker::check`] and the worker exits to be restarted.
+pub struct Broker {
+    publisher: Channel,
+    shard_count: u32,
+    local: Arc<Mutex<LocalQueues>>,
+    consumer_error: Arc<Mutex<Option<lapin::Error>>>,
+    inflight: DashMap<u64, Acker>,
+    // Keeps the connection open for the lifetime of the broker
+    _connection: Connection,
+}
+
+impl Broker {
+    pub async fn connect(config: &AppConfig) -> Result<Self> {
+        let connection = Connection::connect(&config.amqp_url, ConnectionProperties::default()).await?;
+        let shard_count = config.shard_count.max(1);
+
+        let publisher = connection.create_channel().await?;
+        publisher.confirm_select(ConfirmSelectOptions::default()).await?;
+        publisher
+            .exchange_declare(
+                EXCHANGE,
+                ExchangeKind::Direct,
+                ExchangeDeclareOptions { durable: true, ..Default::default() },
+                FieldTable::default(),
+            )
+            .await?;
+
+        // Every worker declares every shard, so publishing never routes into nothing
+        let mut queue_args = FieldTable::default();
+        queue_args.insert("x-max-priority".into(), AMQPValue::ShortShortUInt(MAX_PRIORITY));
+        for shard in 0..shard_count {
+            publisher
+                .queue_declare(
+                    &queue_name(shard),
+                    QueueDeclareOptions { durable: true, ..Default::default() },
+                    queue_args.clone(),
+                )
+                .await?;
+            publisher
+                .queue_bind(
+                    &queue_name(shard),
+                    EXCHANGE,
+                    &routing_key(shard),
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+        }
+
+        let consumer_channel = connection.create_channel().await?;
+        consumer_channel
+            .basic_qos(config.amqp_prefetch, BasicQosOptions { global: true })
+            .await?;
+
+        let local = Arc::new(Mutex::new(LocalQueues::default()));
+        let consumer_error = Arc::new(Mutex::new(None));
+        let worker_count = config.worker_count.max(1);
+        let owned: Vec<u32> = (0..shard_count)
+            .filter(|shard| shard % worker_count == config.worker_index)
+            .collect();
+
+        for shard in &owned {
+            let consumer = consumer_channel
+                .basic_consume(
+                    &queue_name(*shard),
+                    &format!("crawler-{}-shard-{}", config.worker_index, shard),
+                    BasicConsumeOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+            tokio::spawn(receive(consumer, local.clone(), consumer_error.clone()));
+        }
+
+        info!(
+            "Connected to RabbitMQ, worker {}/{} consuming shards {:?}",
+            config.worker_index, worker_count, owned
+        );
+
+        Ok(Self {
+            publisher,
+            shard_count,
+            local,
+            consumer_error,
+            inflight: DashMap::new(),
+            _connection: connection,
+        })
+    }
+
+    /// Publishes a URL to the shard of its host and waits for the broker to confirm it.
+    pub async fn publish(&self, url: &str, host: &str, priority: Priority) -> Result<()> {
+        let properties = BasicProperties::default()
+            .with_delivery_mode(2)
+            .with_priority(MAX_PRIORITY - priority as u8);
+
+        let confirmation = self.publisher
+            .basic_publish(
+                EXCHANGE,
+                &routing_key(shard_of(host, self.shard_count)),
+                BasicPublishOptions::default(),
+                url.as_bytes(),
+                properties,
+            )
+            .await?
+            .await?;
+        if let Confirmation::Nack(_) = confirmation {
+            return Err(CrawlerError::Rejected(url.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn ready_hosts(&self, limit: usize) -> Vec<String> {
+        let local = self.local.lock().unwrap();
+        let now = Instant::now();
+        let mut ready: Vec<(&String, &Instant)> = local.ready_at.iter()
+            .filter(|(_, at)| **at <= now)
+            .collect();
+        ready.sort_by_key(|(_, at)| **at);
+        ready.into_iter().take(limit).map(|(host, _)| host.clone()).collect()
+    }
+
+    pub fn next_ready_in(&self) -> Option<Duration> {
+        let local = self.local.lock().unwrap();
+        let now = Instant::now();
+        local.ready_at.values().min().map(|at| at.saturating_duration_since(now))
+    }
+
+    /// Holds `host` back for `wait`, also when none of its deliveries are
+    /// queued right now, so ones arriving meanwhile wait as well.
+    pub fn defer_host(&self, host: &str, wait: Duration) {
+        let mut local = self.local.lock().unwrap();
+        local.ready_at.insert(host.to_string(), Instant::now() + wait);
+    }
+
+    /// Takes the next delivery of `host`, keeping its acker until the lease is settled.
+    pub fn dequeue_from(&self, host: &str) -> Option<(String, u64)> {
+        let mut local = self.local.lock().unwrap();
+        let Some((url, tag, acker)) = local.queues.get_mut(host).and_then(VecDeque::pop_front) else {
+            // Deferred while it had nothing queued
+            local.ready_at.remove(host);
+            return None;
+        };
+
+        if local.queues.get(host).is_some_and(|q| q.is_empty()) {
+            local.queues.remove(host);
+            local.ready_at.remove(host);
+        } else {
+            local.ready_at.insert(host.to_string(), Instant::now());
+        }
+        drop(local);
+
+        self.inflight.insert(tag, acker);
+        Some((url, tag))
+    }
+
+    pub async fn ack(&self, delivery_tag: u64) -> Result<()> {
+        if let Some((_, acker)) = self.inflight.remove(&delivery_tag) {
+            acker.ack(BasicAckOptions::default()).await?;
+        }
+        Ok(())
+    }
+
+    /// Hands the delivery back to RabbitMQ so it is delivered again.
+    pub async fn release(&self, delivery_tag: u64) -> Result<()> {
+        if let Some((_, acker)) = self.inflight.remove(&delivery_tag) {
+            acker.nack(BasicNackOptions { requeue: true, ..Default::default() }).await?;
+        }
+        Ok(())
+    }
+
+    pub fn pending_hosts(&self) -> usize {
+        self.local.lock().unwrap().queues.len()
+    }
+
+    /// Fails once a consumer has stopped, as no more deliveries would arrive.
+    pub fn check(&self) -> Result<()> {
+        match self.consumer_error.lock().unwrap().clone() {
+            Some(e) => Err(CrawlerError::Queue(e)),
+            None => Ok(()),
+        }
+    }
+}
+
+async fn receive(mut consumer: lapin::Consumer, local: Arc<Mutex<LocalQueues>>, consumer_error: Arc<Mutex<Option<lapin::Error>>>) {
+    let tag = consumer.tag();
+    let e = loop {
+        let delivery = match consumer.next().await {
+            Some(Ok(d)) => d,
+            Some(Err(e)) => break e,
+            // Cancelled by the server, e.g. because the queue was deleted
+            None => break lapin::Error::InvalidChannelState(ChannelState::Closed),
+        };
+
+        let url = String::from_utf8(delivery.data).ok();
+        let host = url.as_deref()
+            .and_then(|u| Url::parse(u).ok())
+            .and_then(|u| u.host_str().map(|h| h.to_string()));
+
+        match (url, host) {
+            (Some(url), Some(host)) => {
+                let mut local = local.lock().unwrap();
+                local.ready_at.entry(host.clone()).or_insert_with(Instant::now);
+                local.queues.entry(host).or_default().push_back((url, delivery.delivery_tag, delivery.acker));
+            }
+            _ => {
+                warn!("Dropping malformed crawl message");
+                let _ = delivery.acker.ack(BasicAckOptions::default()).await;
+            }
+        }
+    };
+    error!("RabbitMQ consumer {} stopped: {}", tag, e);
+    consumer_error.lock().unwrap().get_or_insert(e);
+}
+
+fn queue_name(shard: u32) -> String {
+    format!("crawl.shard.{}", shard)
+}
+
+fn routing_key(shard: u32) -> String {
+    format!("shard.{}", shard)
+}
+
+/// Stable host-to-shard mapping (FNV-1a), identical in every worker process.
+fn shard_of(host: &str, shard_count: u32) -> u32 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in host.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % shard_count as u64) as u32
+}
@@ -0,0 +1,255 @@
+use ego_tree::NodeId;
+use scraper::{ElementRef, Node};
+use std::collections::{HashMap, HashSet};
+
+/// Elements whose text is code, never content.
+pub const CODE_TAGS: &[&str] = &["script", "style", "noscript", "template"];
+
+/// Page furniture that never holds the article itself.
+const BOILERPLATE_TAGS: &[&str] = &["nav", "aside", "footer", "header", "form", "button", "select", "iframe", "menu"];
+
+/// Class or id words of blocks likely to hold the article.
+const POSITIVE_HINTS: &[&str] = &["article", "body", "content", "detail", "entry", "main", "news", "post", "story", "text"];
+
+/// Class or id words of navigation, ads, widgets and the like.
+const NEGATIVE_HINTS: &[&str] = &[
+    "ad", "ads", "advert", "banner", "breadcrumb", "comment", "cookie", "footer", "menu", "nav",
+    "popup", "related", "share", "sidebar", "social", "sponsor", "subscribe", "tags", "trending", "widget",
+];
+
+/// Elements scored by the text they hold directly.
+const SCORED_TAGS: &[&str] = &["p", "pre", "td", "blockquote"];
+
+/// Elements that break text into blocks. A `div` without any is scored like a paragraph.
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "blockquote", "div", "dl", "fieldset", "figure", "h1", "h2", "h3", "h4", "h5", "h6",
+    "ol", "p", "pre", "section", "table", "ul",
+];
+
+/// Shorter paragraphs are captions, bylines and buttons rather than prose.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Less text than this in the best block means the page has no article to speak of.
+const MIN_CONTENT_CHARS: usize = 140;
+
+/// Blocks inside the article that are mostly links and this short are link lists.
+const LINK_LIST_MAX_CHARS: usize = 200;
+
+/// Where the main content of a page is, and what to leave out of it.
+pub struct MainContent<'a> {
+    pub root: ElementRef<'a>,
+    /// Boilerplate elements below `root` whose text is skipped
+    pub excluded: HashSet<NodeId>,
+}
+
+/// Finds the block of `body` holding the page's main text, readability style:
+/// paragraphs score by length and punctuation, pass the score on to their
+/// parent and grandparent, class names nudge it, and the more of a block's
+/// text is links the less it keeps. Returns `None` when no block has enough text.
+pub fn main_content(body: ElementRef<'_>) -> Option<MainContent<'_>> {
+    let mut scores: HashMap<NodeId, f64> = HashMap::new();
+
+    for el in body.descendants().filter_map(ElementRef::wrap) {
+        let name = el.value().name();
+        let scored = SCORED_TAGS.contains(&name) || (name == "div" && !has_block_children(el));
+        if !scored || is_boilerplate(el) || is_within(el, body, |a| CODE_TAGS.contains(&a.value().name()) || is_boilerplate(a)) {
+            continue;
+        }
+
+        let text = collapsed_text(el);
+        let chars = text.chars().count();
+        if chars < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        // Commas and the Devanagari danda both mark running prose
+        let punctuation = text.chars().filter(|c| matches!(c, ',' | '।' | '.')).count();
+        let score = 1.0 + punctuation as f64 + (chars as f64 / 100.0).min(3.0);
+
+        for (ancestor, share) in el.ancestors().filter_map(ElementRef::wrap).take(2).zip([1.0, 0.5]) {
+            *scores.entry(ancestor.id()).or_insert_with(|| initial_score(ancestor)) += score * share;
+        }
+    }
+
+    let document = body.tree();
+    let root = scores.into_iter()
+        .filter_map(|(id, score)| Some((ElementRef::wrap(document.get(id)?)?, score)))
+        .map(|(el, score)| (el, score * (1.0 - link_density(el))))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(el, _)| el)?;
+
+    let excluded: HashSet<NodeId> = root.descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+        .filter(|el| is_boilerplate(*el) || is_link_list(*el))
+        .map(|el| el.id())
+        .collect();
+
+    let content = MainContent { root, excluded };
+    (content.text_len() >= MIN_CONTENT_CHARS).then_some(content)
+}
+
+/// Boilerplate elements anywhere below `root`, for text without the page furniture.
+pub fn boilerplate(root: ElementRef<'_>) -> HashSet<NodeId> {
+    root.descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|el| BOILERPLATE_TAGS.contains(&el.value().name()))
+        .map(|el| el.id())
+        .collect()
+}
+
+impl MainContent<'_> {
+    fn text_len(&self) -> usize {
+        self.root.descendants()
+            .filter_map(|node| match node.value() {
+                Node::Text(text) => Some((node, text)),
+                _ => None,
+            })
+            .filter(|(node, _)| !node.ancestors().any(|a| self.excluded.contains(&a.id())))
+            .map(|(_, text)| text.trim().chars().count())
+            .sum()
+    }
+}
+
+fn initial_score(el: ElementRef) -> f64 {
+    let tag_bonus = match el.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "td" | "blockquote" | "pre" => 3.0,
+        "li" | "ol" | "ul" | "form" => -3.0,
+        _ => 0.0,
+    };
+    tag_bonus + class_weight(el)
+}
+
+/// +25 for a class or id that suggests content, -25 for one that suggests furniture.
+fn class_weight(el: ElementRef) -> f64 {
+    let mut weight = 0.0;
+    let words = hint_words(el);
+    if words.iter().any(|w| NEGATIVE_HINTS.contains(&w.as_str())) {
+        weight -= 25.0;
+    }
+    if words.iter().any(|w| POSITIVE_HINTS.contains(&w.as_str())) {
+        weight += 25.0;
+    }
+    weight
+}
+
+/// Words of the class and id attributes, e.g. `post-body` gives `post` and `body`.
+fn hint_words(el: ElementRef) -> Vec<String> {
+    let value = el.value();
+    value.classes()
+        .chain(value.id())
+        .flat_map(|name| name.split(|c: char| !c.is_ascii_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn is_boilerplate(el: ElementRef) -> bool {
+    BOILERPLATE_TAGS.contains(&el.value().name()) || class_weight(el) < 0.0
+}
+
+fn is_link_list(el: ElementRef) -> bool {
+    if !BLOCK_TAGS.contains(&el.value().name()) {
+        return false;
+    }
+    let chars = collapsed_text(el).chars().count();
+    chars > 0 && chars < LINK_LIST_MAX_CHARS && link_density(el) > 0.5
+}
+
+/// Whether an ancestor of `el` below `root` matches. `root` and what is above
+/// it are left out, so a class on `<body>` such as `has-sidebar` does not
+/// make the whole page boilerplate.
+fn is_within(el: ElementRef, root: ElementRef, predicate: impl Fn(ElementRef) -> bool) -> bool {
+    el.ancestors()
+        .take_while(|a| a.id() != root.id())
+        .filter_map(ElementRef::wrap)
+        .any(predicate)
+}
+
+fn has_block_children(el: ElementRef) -> bool {
+    el.children()
+        .filter_map(ElementRef::wrap)
+        .any(|child| BLOCK_TAGS.contains(&child.value().name()))
+}
+
+/// Share of the text of `el` that sits inside links.
+fn link_density(el: ElementRef) -> f64 {
+    let total = collapsed_text(el).chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+    let links: usize = el.descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|a| a.value().name() == "a")
+        .map(|a| collapsed_text(a).chars().count())
+        .sum();
+    (links as f64 / total as f64).min(1.0)
+}
+
+fn collapsed_text(el: ElementRef) -> String {
+    el.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::{Html, Selector};
+
+    const PARAGRAPH: &str = "काठमाडौं महानगरपालिकाले आगामी आर्थिक वर्षको बजेट सार्वजनिक गरेको छ, जसमा सडक, खानेपानी र \
+        फोहोर व्यवस्थापनका लागि ठूलो रकम छुट्याइएको छ। नगर प्रमुखले यो बजेट नागरिकमुखी भएको बताए।";
+
+    /// Text of the main content of `html`, without the excluded elements.
+    fn main_text(html: &str) -> Option<(Option<String>, String)> {
+        let document = Html::parse_document(html);
+        let body = document.select(&Selector::parse("body").unwrap()).next().unwrap();
+        let main = main_content(body)?;
+        let text = main.root.descendants()
+            .filter(|node| !node.ancestors().any(|a| main.excluded.contains(&a.id())))
+            .filter_map(|node| node.value().as_text().map(|text| text.trim().to_string()))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some((main.root.value().attr("id").map(str::to_string), text))
+    }
+
+    #[test]
+    fn finds_the_article_among_the_furniture() {
+        let html = format!(r#"<html><body class="news-page">
+            <header><nav><a href="/">गृहपृष्ठ</a> <a href="/news">समाचार</a></nav></header>
+            <div id="sidebar" class="sidebar"><p>{PARAGRAPH}</p></div>
+            <div id="story" class="article-content">
+              <h1>बजेट सार्वजनिक</h1>
+              <p>{PARAGRAPH}</p>
+              <p>{PARAGRAPH}</p>
+              <ul><li><a href="/a">सम्बन्धित समाचार एक</a></li><li><a href="/b">सम्बन्धित समाचार दुई</a></li></ul>
+              <div class="share">Share this</div>
+            </div>
+            <footer><p>{PARAGRAPH}</p></footer>
+            </body></html>"#);
+
+        let (id, text) = main_text(&html).unwrap();
+        assert_eq!(id.as_deref(), Some("story"));
+        assert!(text.starts_with("बजेट सार्वजनिक"));
+        assert!(!text.contains("सम्बन्धित समाचार"));
+        assert!(!text.contains("Share this"));
+    }
+
+    #[test]
+    fn skips_pages_without_enough_text() {
+        let html = r#"<html><body><nav><a href="/">Home</a></nav><div><p>Only a short caption here.</p></div></body></html>"#;
+        assert!(main_text(html).is_none());
+    }
+
+    #[test]
+    fn ignores_paragraphs_inside_boilerplate_and_scripts() {
+        let html = format!(r#"<html><body>
+            <aside id="aside"><p>{PARAGRAPH}</p><p>{PARAGRAPH}</p><p>{PARAGRAPH}</p></aside>
+            <noscript><p>{PARAGRAPH}</p></noscript>
+            <div id="story"><p>{PARAGRAPH}</p></div>
+            </body></html>"#);
+
+        let (id, _) = main_text(&html).unwrap();
+        assert_eq!(id.as_deref(), Some("story"));
+    }
+}
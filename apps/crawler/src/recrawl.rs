@@ -0,0 +1,125 @@
+use fred::prelude::*;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tracing::info;
+use crate::error::Result;
+use crate::frontier::{claim_due, now_millis};
+use crate::storage::Storage;
+
+const SCHEDULE_KEY: &str = "recrawl:schedule";
+
+/// Decides when each page is crawled again, based on how often it changed.
+///
+/// Every fetch is compared with the previous content hash. Pages that changed
+/// get their interval halved, unchanged pages get it stretched, so busy news
+/// homepages settle at a few minutes and static pages drift towards the
+/// maximum. Pages seen for the first time start from their host's observed
+/// change ratio. Due URLs wait in the `recrawl:schedule` sorted set (scored
+/// by due time). Their `visited:{url}` markers stay, so links found in the
+/// meantime cannot queue them a second time; only the schedule does.
+#[derive(Clone)]
+pub struct RecrawlScheduler {
+    redis: Client,
+    min_interval: Duration,
+    max_interval: Duration,
+}
+
+impl RecrawlScheduler {
+    pub fn new(redis: Client, min_interval: Duration, max_interval: Duration) -> Self {
+        Self {
+            redis,
+            min_interval,
+            max_interval: max_interval.max(min_interval),
+        }
+    }
+
+    /// Records one fetch of a page on `host` and returns the interval until
+    /// the next one. `changed` is `None` on a first crawl, when there is
+    /// nothing to compare with.
+    pub async fn observe(&self, host: &str, previous_interval: Option<Duration>, changed: Option<bool>) -> Result<Duration> {
+        if let Some(changed) = changed {
+            let stats_key = host_stats_key(host);
+            self.redis.hincrby::<(), _, _>(&stats_key, "checks", 1).await?;
+            if changed {
+                self.redis.hincrby::<(), _, _>(&stats_key, "changes", 1).await?;
+            }
+        }
+
+        let interval = match (previous_interval, changed) {
+            (Some(interval), Some(true)) => interval / 2,
+            (Some(interval), Some(false)) => interval.mul_f64(1.5),
+            _ => self.host_interval(host).await?,
+        };
+        Ok(interval.clamp(self.min_interval, self.max_interval))
+    }
+
+    /// Schedules the next crawl of `url`. Its visited marker is rewritten
+    /// without an expiry, dropping the one older markers were set with.
+    pub async fn schedule(&self, url: &str, interval: Duration) -> Result<()> {
+        let key = format!("visited:{}", url);
+        self.redis.set::<(), _, _>(&key, "1", None, None, false).await?;
+
+        let due_at = now_millis() + interval.as_millis() as i64;
+        self.redis
+            .zadd::<(), _, _>(SCHEDULE_KEY, None, None, false, false, (due_at as f64, url))
+            .await?;
+        Ok(())
+    }
+
+    /// Number of URLs waiting for their next crawl.
+    pub async fn scheduled_len(&self) -> Result<usize> {
+        Ok(self.redis.zcard(SCHEDULE_KEY).await?)
+    }
+
+    /// Claims up to `limit` URLs whose next crawl is due.
+    pub async fn take_due(&self, limit: usize) -> Result<Vec<String>> {
+        claim_due(&self.redis, SCHEDULE_KEY, limit).await
+    }
+
+    /// Fills an empty schedule from `documents`, e.g. after upgrading or
+    /// losing Redis. Due times are derived from
+    /// `crawled_at` and the stored interval.
+    pub async fn bootstrap(&self, storage: &Storage) -> Result<()> {
+        if self.scheduled_len().await? > 0 {
+            return Ok(());
+        }
+
+        let default_interval = self.max_interval.as_secs() as i64;
+        let due = storage.recrawl_due_times(default_interval).await?;
+        for chunk in due.chunks(1000) {
+            let values: Vec<(f64, String)> = chunk.iter()
+                .map(|(url, due_at)| (due_at.timestamp_millis() as f64, url.clone()))
+                .collect();
+            self.redis
+                .zadd::<(), _, _>(SCHEDULE_KEY, None, None, false, false, values)
+                .await?;
+        }
+
+        if !due.is_empty() {
+            info!("Recrawl schedule bootstrapped with {} documents", due.len());
+        }
+        Ok(())
+    }
+
+    /// Starting interval for new pages of `host`: the minimum for hosts
+    /// whose pages always change, the maximum for hosts that never change.
+    async fn host_interval(&self, host: &str) -> Result<Duration> {
+        let (checks, changes): (Option<u64>, Option<u64>) = self.redis
+            .hmget(host_stats_key(host), vec!["checks", "changes"])
+            .await?;
+
+        // Laplace smoothing keeps hosts we know little about in the middle
+        let change_ratio = (changes.unwrap_or(0) as f64 + 1.0) / (checks.unwrap_or(0) as f64 + 2.0);
+        let span = self.max_interval.as_secs_f64() / self.min_interval.as_secs_f64().max(1.0);
+        Ok(self.min_interval.mul_f64(span.powf(1.0 - change_ratio.min(1.0))))
+    }
+}
+
+/// Hex SHA-256 of the extracted text, used to tell whether a page changed.
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+fn host_stats_key(host: &str) -> String {
+    format!("recrawl:host:{}", host)
+}
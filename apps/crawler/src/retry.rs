@@ -0,0 +1,81 @@
+use fred::prelude::*;
+use rand::Rng;
+use std::time::Duration;
+use crate::error::Result;
+use crate::frontier::{claim_due, now_millis};
+
+/// Failed attempts per URL, cleared once it succeeds or is given up.
+const ATTEMPTS_KEY: &str = "retry:attempts";
+/// URLs waiting for their next attempt, scored by when (ms) it is due.
+const SCHEDULE_KEY: &str = "retry:schedule";
+
+/// What to do with a URL after a transient failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Try again after the delay; this was failed attempt number `attempt`
+    RetryIn { delay: Duration, attempt: u32 },
+    /// The attempt limit is reached
+    GiveUp { attempts: u32 },
+}
+
+/// Retries URLs that failed for reasons that may go away, such as timeouts
+/// and 503s, with exponential backoff and jitter. Attempts are counted in
+/// Redis so the limit holds across workers and restarts.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    redis: Client,
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(redis: Client, max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            redis,
+            max_attempts: max_attempts.max(1),
+            base_delay,
+            max_delay: max_delay.max(base_delay),
+        }
+    }
+
+    /// Counts a failed attempt of `url` and schedules the next one if any are left.
+    pub async fn failed(&self, url: &str) -> Result<RetryDecision> {
+        let attempt: i64 = self.redis.hincrby(ATTEMPTS_KEY, url, 1).await?;
+        let attempt = attempt.max(1) as u32;
+        if attempt >= self.max_attempts {
+            self.clear(url).await?;
+            return Ok(RetryDecision::GiveUp { attempts: attempt });
+        }
+
+        let delay = self.backoff(attempt);
+        let due_at = now_millis() + delay.as_millis() as i64;
+        self.redis
+            .zadd::<(), _, _>(SCHEDULE_KEY, None, None, false, false, (due_at as f64, url))
+            .await?;
+        Ok(RetryDecision::RetryIn { delay, attempt })
+    }
+
+    /// Forgets the failed attempts of `url`, e.g. after it finally succeeded.
+    pub async fn clear(&self, url: &str) -> Result<()> {
+        self.redis.hdel::<(), _, _>(ATTEMPTS_KEY, url).await?;
+        Ok(())
+    }
+
+    /// Claims up to `limit` URLs whose next attempt is due.
+    pub async fn take_due(&self, limit: usize) -> Result<Vec<String>> {
+        claim_due(&self.redis, SCHEDULE_KEY, limit).await
+    }
+
+    pub async fn scheduled_len(&self) -> Result<usize> {
+        Ok(self.redis.zcard(SCHEDULE_KEY).await?)
+    }
+
+    /// Doubles with every attempt up to the maximum. The jitter keeps URLs
+    /// that failed together (a host going down) from all coming back at once.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(1u32 << (attempt - 1).min(20));
+        let capped = exponential.min(self.max_delay);
+        capped.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
@@ -0,0 +1,370 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use fred::prelude::*;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashSet;
+use std::io::Read;
+use std::time::Duration;
+use tracing::{debug, warn};
+use crate::error::{CrawlerError, Result};
+use crate::fetcher::{Fetcher, Validators};
+use crate::frontier::{claim_due, host_of, now_millis};
+use crate::politeness::PolitenessManager;
+
+/// News sitemaps to poll again, scored by when (ms) they are due.
+const NEWS_KEY: &str = "sitemap:news";
+
+/// The protocol caps a sitemap at 50 MB uncompressed.
+const MAX_SITEMAP_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Sitemap indexes may nest, but never this deep in practice.
+const MAX_INDEX_DEPTH: usize = 3;
+
+/// One `<url>` of a sitemap.
+#[derive(Debug, Clone)]
+pub struct SitemapEntry {
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+    pub priority: Option<f32>,
+    /// Listed with a `<news:news>` block, i.e. a recent article
+    pub news: bool,
+}
+
+/// Everything found by expanding one sitemap and the indexes below it.
+#[derive(Debug, Default)]
+pub struct SitemapUrls {
+    pub entries: Vec<SitemapEntry>,
+    /// Sitemaps that listed news entries, worth polling often
+    pub news_sitemaps: Vec<String>,
+}
+
+enum Sitemap {
+    Index(Vec<String>),
+    UrlSet(Vec<SitemapEntry>),
+}
+
+/// Finds URLs through sitemaps.
+///
+/// Each host is looked at once per refresh period: the sitemaps named in its
+/// robots.txt (or `/sitemap.xml` when there are none) are fetched, indexes
+/// are expanded recursively and gzipped files unpacked. News sitemaps are
+/// remembered in `sitemap:news` and polled on a much shorter period, since
+/// they are the quickest way to learn about new articles.
+#[derive(Clone)]
+pub struct SitemapReader {
+    fetcher: Fetcher,
+    redis: Client,
+    refresh: Duration,
+    news_poll: Duration,
+    max_urls: usize,
+}
+
+impl SitemapReader {
+    pub fn new(fetcher: Fetcher, redis: Client, refresh: Duration, news_poll: Duration, max_urls: usize) -> Self {
+        Self { fetcher, redis, refresh, news_poll, max_urls }
+    }
+
+    /// True if the sitemaps of `host` are due for a read. Only one worker gets true per refresh period.
+    pub async fn claim_host(&self, host: &str) -> Result<bool> {
+        let key = format!("sitemap:host:{}", host);
+        let secs = self.refresh.as_secs().max(1) as i64;
+        let fresh: Option<String> = self.redis
+            .set(&key, "1", Some(Expiration::EX(secs)), Some(SetOptions::NX), false)
+            .await?;
+        Ok(fresh.is_some())
+    }
+
+    /// Sitemaps to start from for the site of `url`.
+    pub async fn roots(&self, url: &str, politeness: &PolitenessManager) -> Vec<String> {
+        let mut roots = politeness.sitemaps(url).await;
+        if roots.is_empty() {
+            if let Ok(url) = url::Url::parse(url) {
+                if let Some(host) = url.host_str() {
+                    roots.push(format!("{}://{}/sitemap.xml", url.scheme(), host));
+                }
+            }
+        }
+        roots
+    }
+
+    /// Fetches `sitemap_url` and every sitemap it indexes, up to the URL limit.
+    /// Fetches go through robots.txt and the host rate limit like any page.
+    /// Fails if `sitemap_url` itself cannot be fetched or parsed; broken
+    /// sitemaps below it are skipped.
+    pub async fn read(&self, sitemap_url: &str, politeness: &PolitenessManager) -> Result<SitemapUrls> {
+        let mut found = SitemapUrls::default();
+        let mut seen = HashSet::new();
+        let mut pending = vec![(sitemap_url.to_string(), 0)];
+
+        while let Some((url, depth)) = pending.pop() {
+            if !seen.insert(url.clone()) || found.entries.len() >= self.max_urls {
+                continue;
+            }
+            if !politeness.is_allowed(&url).await {
+                debug!("Sitemap disallowed by robots.txt: {}", url);
+                continue;
+            }
+            if let Some(host) = host_of(&url) {
+                politeness.acquire(&host).await;
+            }
+
+            let body = match self.fetch(&url, politeness).await {
+                Ok(body) => body,
+                Err(e) if depth == 0 => return Err(e),
+                Err(e) => {
+                    warn!("Sitemap fetch failed {}: {}", url, e);
+                    continue;
+                }
+            };
+
+            match parse_sitemap(&body) {
+                Ok(Sitemap::Index(children)) if depth < MAX_INDEX_DEPTH => {
+                    pending.extend(children.into_iter().map(|child| (child, depth + 1)));
+                }
+                Ok(Sitemap::Index(_)) => debug!("Sitemap index nested too deep: {}", url),
+                Ok(Sitemap::UrlSet(entries)) => {
+                    if entries.iter().any(|e| e.news) {
+                        found.news_sitemaps.push(url.clone());
+                    }
+                    let room = self.max_urls - found.entries.len();
+                    found.entries.extend(entries.into_iter().take(room));
+                }
+                Err(e) if depth == 0 => return Err(e),
+                Err(e) => warn!("Invalid sitemap {}: {}", url, e),
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Puts a news sitemap (back) on the polling schedule.
+    pub async fn watch_news(&self, sitemap_url: &str) -> Result<()> {
+        let due_at = now_millis() + self.news_poll.as_millis() as i64;
+        self.redis
+            .zadd::<(), _, _>(NEWS_KEY, None, None, false, false, (due_at as f64, sitemap_url))
+            .await?;
+        Ok(())
+    }
+
+    /// Claims the news sitemaps that are due for a poll.
+    pub async fn take_due_news(&self, limit: usize) -> Result<Vec<String>> {
+        claim_due(&self.redis, NEWS_KEY, limit).await
+    }
+
+    /// Raw sitemap XML, unpacked if it was served gzipped.
+    async fn fetch(&self, url: &str, politeness: &PolitenessManager) -> Result<Vec<u8>> {
+        let fetched = self.fetcher.fetch_conditional_with_limit(url, &Validators::default(), MAX_SITEMAP_BYTES).await;
+        if let Some(host) = host_of(url) {
+            politeness.record_fetch(&host, &fetched);
+        }
+        let fetched = fetched?;
+        if !fetched.status.is_success() {
+            return Err(CrawlerError::Parse(format!("HTTP {}", fetched.status)));
+        }
+        // A cut off document would parse as a partial list and hide the rest
+        if fetched.truncated {
+            return Err(CrawlerError::TooLarge(MAX_SITEMAP_BYTES));
+        }
+
+        // Checked by magic bytes, servers label .xml.gz files inconsistently
+        if fetched.body.starts_with(&[0x1f, 0x8b]) {
+            let mut xml = Vec::new();
+            GzDecoder::new(fetched.body.as_slice())
+                .take(MAX_SITEMAP_BYTES + 1)
+                .read_to_end(&mut xml)?;
+            if xml.len() as u64 > MAX_SITEMAP_BYTES {
+                return Err(CrawlerError::TooLarge(MAX_SITEMAP_BYTES));
+            }
+            return Ok(xml);
+        }
+        Ok(fetched.body)
+    }
+}
+
+/// Parses a `<urlset>` or `<sitemapindex>`. Plain text sitemaps, one URL per
+/// line, are accepted as well.
+fn parse_sitemap(body: &[u8]) -> Result<Sitemap> {
+    let text = String::from_utf8_lossy(body);
+    let text = text.trim_start_matches('\u{feff}');
+    if !text.trim_start().starts_with('<') {
+        let entries = text.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("http://") || line.starts_with("https://"))
+            .map(|loc| SitemapEntry { loc: loc.to_string(), lastmod: None, priority: None, news: false })
+            .collect();
+        return Ok(Sitemap::UrlSet(entries));
+    }
+
+    let mut reader = Reader::from_reader(body);
+    reader.config_mut().trim_text(true);
+
+    let mut is_index = false;
+    let mut sitemaps = Vec::new();
+    let mut entries = Vec::new();
+    let mut current: Option<SitemapEntry> = None;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                // Local names, so `news:news` and prefixed sitemap elements match too
+                let name = e.local_name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"sitemapindex" => is_index = true,
+                    b"url" | b"sitemap" => {
+                        current = Some(SitemapEntry { loc: String::new(), lastmod: None, priority: None, news: false });
+                    }
+                    b"news" => {
+                        if let Some(entry) = current.as_mut() {
+                            entry.news = true;
+                        }
+                    }
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Ok(Event::Text(e)) => {
+                let value = e.unescape().map_err(|e| CrawlerError::Parse(e.to_string()))?;
+                set_field(current.as_mut(), &path, value.trim());
+            }
+            Ok(Event::CData(e)) => {
+                let value = String::from_utf8_lossy(&e);
+                set_field(current.as_mut(), &path, value.trim());
+            }
+            Ok(Event::End(e)) => {
+                if matches!(e.local_name().as_ref(), b"url" | b"sitemap") {
+                    if let Some(entry) = current.take().filter(|entry| !entry.loc.is_empty()) {
+                        if is_index {
+                            sitemaps.push(entry.loc);
+                        } else {
+                            entries.push(entry);
+                        }
+                    }
+                }
+                path.pop();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(CrawlerError::Parse(e.to_string())),
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(if is_index { Sitemap::Index(sitemaps) } else { Sitemap::UrlSet(entries) })
+}
+
+/// Stores text found at `path` on the entry being read. Only direct children
+/// of `<url>` count, so e.g. the `<image:loc>` of an image extension is ignored.
+fn set_field(entry: Option<&mut SitemapEntry>, path: &[Vec<u8>], value: &str) {
+    let (Some(entry), [.., parent, element]) = (entry, path) else {
+        return;
+    };
+    match (parent.as_slice(), element.as_slice()) {
+        (b"url" | b"sitemap", b"loc") => entry.loc = value.to_string(),
+        (b"url" | b"sitemap", b"priority") => entry.priority = value.parse().ok(),
+        // Articles in news sitemaps carry their date in <news:publication_date>
+        (b"url" | b"sitemap", b"lastmod") | (b"news", b"publication_date") => {
+            if let Some(date) = parse_w3c_date(value) {
+                entry.lastmod = Some(entry.lastmod.map_or(date, |d| d.max(date)));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// W3C datetime as used by sitemaps: a full timestamp, one without seconds, or just a date.
+pub(crate) fn parse_w3c_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M%:z") {
+        return Some(date.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url_set(body: &str) -> Vec<SitemapEntry> {
+        match parse_sitemap(body.as_bytes()).unwrap() {
+            Sitemap::UrlSet(entries) => entries,
+            Sitemap::Index(_) => panic!("parsed as an index"),
+        }
+    }
+
+    #[test]
+    fn reads_url_sets() {
+        let entries = url_set(r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                    xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+              <url>
+                <loc>https://example.com/a?x=1&amp;y=2</loc>
+                <lastmod>2024-06-29T10:00:00+05:45</lastmod>
+                <priority>0.8</priority>
+                <image:image><image:loc>https://cdn.example.com/a.jpg</image:loc></image:image>
+              </url>
+              <url><loc><![CDATA[https://example.com/b]]></loc><lastmod>2024-06-29</lastmod></url>
+              <url><lastmod>2024-06-29</lastmod></url>
+            </urlset>"#);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].loc, "https://example.com/a?x=1&y=2");
+        assert_eq!(entries[0].lastmod, parse_w3c_date("2024-06-29T04:15:00Z"));
+        assert_eq!(entries[0].priority, Some(0.8));
+        assert!(!entries[0].news);
+        assert_eq!(entries[1].loc, "https://example.com/b");
+        assert_eq!(entries[1].lastmod, parse_w3c_date("2024-06-29T00:00:00Z"));
+    }
+
+    #[test]
+    fn reads_news_entries() {
+        let entries = url_set(r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                xmlns:news="http://www.google.com/schemas/sitemap-news/0.9">
+              <url>
+                <loc>https://example.com/news/1</loc>
+                <news:news>
+                  <news:publication><news:name>Example</news:name><news:language>ne</news:language></news:publication>
+                  <news:publication_date>2024-06-29T10:00:00Z</news:publication_date>
+                  <news:title>Title</news:title>
+                </news:news>
+              </url>
+            </urlset>"#);
+
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].news);
+        assert_eq!(entries[0].lastmod, parse_w3c_date("2024-06-29T10:00:00Z"));
+    }
+
+    #[test]
+    fn reads_indexes() {
+        let body = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://example.com/sitemap-1.xml</loc><lastmod>2024-06-29</lastmod></sitemap>
+              <sitemap><loc>https://example.com/sitemap-2.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+        match parse_sitemap(body.as_bytes()).unwrap() {
+            Sitemap::Index(sitemaps) => assert_eq!(sitemaps, [
+                "https://example.com/sitemap-1.xml",
+                "https://example.com/sitemap-2.xml.gz",
+            ]),
+            Sitemap::UrlSet(_) => panic!("parsed as a url set"),
+        }
+    }
+
+    #[test]
+    fn reads_plain_text_sitemaps() {
+        let entries = url_set("\u{feff}https://example.com/a\n  http://example.com/b  \n# comment\nexample.com/c\n");
+        let locs: Vec<&str> = entries.iter().map(|e| e.loc.as_str()).collect();
+        assert_eq!(locs, ["https://example.com/a", "http://example.com/b"]);
+    }
+
+    #[test]
+    fn rejects_broken_xml() {
+        assert!(parse_sitemap(b"<urlset><url><loc>https://example.com/a</loc></urlset>").is_err());
+    }
+}
@@ -1,161 +1,660 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::{mpsc, broadcast};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+use tokio::task::{self, JoinSet};
 use tracing::{info, debug, warn, error};
+use crate::canonical::{self, Canonicalizer};
 use crate::config::AppConfig;
-use crate::fetcher::Fetcher;
-use crate::parser::Parser;
-use crate::storage::Storage;
-use crate::error::Result;
-use crate::politeness::PolitenessManager;
+use crate::fetcher::{FetchResult, Fetcher, HopCheck};
+use crate::frontier::{host_of, Frontier, Lease, Priority};
+use crate::extractor::{self, ContentRouter, Extraction};
+use crate::feed;
+use crate::language;
+use crate::parser::{Parser, RobotsDirectives};
+use crate::storage::{CrawlState, FeedState, NewDocument, Storage};
+use crate::error::{CrawlerError, Result};
+use crate::politeness::{PolitenessManager, RobotsAccess, RobotsManager};
+use crate::queue::Broker;
+use crate::recrawl::{content_hash, RecrawlScheduler};
+use crate::retry::{RetryDecision, RetryPolicy};
+use crate::sitemap::{SitemapEntry, SitemapReader};
+use chrono::Utc;
 use fred::prelude::*;
+use reqwest::StatusCode;
+
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const RECRAWL_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const RECRAWL_BATCH: usize = 1000;
+const SITEMAP_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const SITEMAP_BATCH: usize = 100;
+/// Sitemap reads running at once beside the crawl.
+const SITEMAP_CONCURRENCY: usize = 4;
+const METRICS_INTERVAL: Duration = Duration::from_secs(60);
+const FEED_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const FEED_BATCH: i64 = 50;
+/// A claimed feed is polled again after this if its poller never reports back.
+const FEED_POLL_LEASE: Duration = Duration::from_secs(600);
+/// Sitemap entries modified more recently than this are crawled first.
+const FRESH_SITEMAP_ENTRY: chrono::Duration = chrono::Duration::days(2);
 
 pub struct Spider {
     config: AppConfig,
     fetcher: Fetcher,
-    parser: Parser,
+    content: ContentRouter,
+    canonicalizer: Canonicalizer,
     storage: Storage,
     politeness: Arc<PolitenessManager>,
-    redis: Client,
+    frontier: Frontier,
+    recrawl: RecrawlScheduler,
+    sitemaps: SitemapReader,
+    sitemap_slots: Arc<Semaphore>,
+    retry: RetryPolicy,
     shutdown: broadcast::Sender<()>,
 }
 
 impl Spider {
     pub async fn new(config: &AppConfig) -> Result<Self> {
         let fetcher = Fetcher::new(config)?;
-        let parser = Parser::new();
+        let content = ContentRouter::new(Parser::new()
+            .with_bot_name(config.bot_name())
+            .with_content_selectors(&config.content_selectors));
+        let canonicalizer = Canonicalizer::new(config.canonical_rules.clone());
         let storage = Storage::new(&config.database_url).await?;
-        let politeness = Arc::new(PolitenessManager::new(
-            fetcher.clone(),
-            config.user_agent.clone(),
-            config.rate_limit_per_domain,
-        ));
-
         // Redis configuration for fred v10
         let redis_config = Config::from_url(&config.redis_url)
             .map_err(|e| crate::error::CrawlerError::Redis(format!("Config error: {}", e)))?;
         let redis = Client::new(redis_config, None, None, None);
-        let _ = redis.connect();
-        let _ = redis.wait_for_connect().await
+        redis.init().await
             .map_err(|e| crate::error::CrawlerError::Redis(format!("Connection error: {}", e)))?;
+        let robots = RobotsManager::new(
+            fetcher.clone(),
+            redis.clone(),
+            config.user_agent.clone(),
+            Duration::from_secs(config.robots_ttl_secs),
+            Duration::from_secs(config.robots_error_ttl_secs),
+        );
+        let politeness = Arc::new(PolitenessManager::new(robots, fetcher.dns().clone(), config));
+        let recrawl = RecrawlScheduler::new(
+            redis.clone(),
+            Duration::from_secs(config.recrawl_min_secs),
+            Duration::from_secs(config.recrawl_max_secs),
+        );
+        let sitemaps = SitemapReader::new(
+            fetcher.clone(),
+            redis.clone(),
+            Duration::from_secs(config.sitemap_refresh_secs),
+            Duration::from_secs(config.news_sitemap_poll_secs),
+            config.sitemap_max_urls,
+        );
+        let retry = RetryPolicy::new(
+            redis.clone(),
+            config.retry_max_attempts,
+            Duration::from_secs(config.retry_base_delay_secs),
+            Duration::from_secs(config.retry_max_delay_secs),
+        );
+        let mut frontier = Frontier::new(redis, Duration::from_secs(config.frontier_lease_secs.max(1)));
+        if config.distributed {
+            frontier = frontier.with_broker(Broker::connect(config).await?);
+        }
 
         let (shutdown, _) = broadcast::channel(1);
 
         Ok(Self {
             config: config.clone(),
             fetcher,
-            parser,
+            content,
+            canonicalizer,
             storage,
             politeness,
-            redis,
+            frontier,
+            recrawl,
+            sitemaps,
+            sitemap_slots: Arc::new(Semaphore::new(SITEMAP_CONCURRENCY)),
+            retry,
             shutdown,
         })
     }
 
     pub async fn run(&self, seeds: Vec<String>) -> Result<()> {
-        let mut pending_queue = VecDeque::from(seeds);
-        let mut active_tasks = 0;
-        let (res_tx, mut res_rx) = mpsc::channel(100);
+        // Leases left behind by a crashed run are re-delivered before anything else
+        self.frontier.requeue_expired().await?;
+        self.recrawl.bootstrap(&self.storage).await?;
+        let seeded = self.frontier.enqueue(&self.canonicalize_all(&seeds)).await?;
+        let pending_hosts = self.frontier.pending_hosts().await?;
+
+        let mut workers = JoinSet::new();
+        let mut leases: HashMap<task::Id, Lease> = HashMap::new();
         let mut shutdown_rx = self.shutdown.subscribe();
+        let mut reaper = tokio::time::interval(self.frontier.lease_timeout() / 2);
+        let mut recrawl_timer = tokio::time::interval(RECRAWL_POLL_INTERVAL);
+        let mut sitemap_timer = tokio::time::interval(SITEMAP_POLL_INTERVAL);
+        let mut feed_timer = tokio::time::interval(FEED_POLL_INTERVAL);
+        let mut metrics_timer = tokio::time::interval(METRICS_INTERVAL);
 
-        info!("Starting crawl loop with {} seeds...", pending_queue.len());
+        info!("Starting crawl loop with {} new seeds, {} hosts pending...", seeded, pending_hosts);
 
         loop {
-            tokio::select! {
-                _ = shutdown_rx.recv() => {
-                    info!("Spider shutting down...");
+            // A dead consumer would leave the worker idling forever; exit so it is restarted
+            self.frontier.check_transport()?;
+
+            // Fill free worker slots, spreading them across hosts that are ready
+            let mut idle_wait = None;
+            while workers.len() < self.config.crawler_concurrency {
+                let free = self.config.crawler_concurrency - workers.len();
+                let hosts = self.frontier.ready_hosts(free).await?;
+                if hosts.is_empty() {
+                    idle_wait = Some(self.frontier.next_ready_in().await?
+                        .unwrap_or(IDLE_POLL_INTERVAL)
+                        .min(IDLE_POLL_INTERVAL));
                     break;
                 }
-                
-                // Spawn tasks if we have URLs and haven't exceeded concurrency
-                _ = async {}, if active_tasks < self.config.crawler_concurrency && !pending_queue.is_empty() => {
-                    if let Some(url) = pending_queue.pop_front() {
-                        active_tasks += 1;
+
+                for host in hosts {
+                    if workers.len() >= self.config.crawler_concurrency {
+                        break;
+                    }
+                    if let Err(wait) = self.politeness.try_acquire(&host).await {
+                        self.frontier.defer_host(&host, wait).await?;
+                        continue;
+                    }
+                    if let Some(lease) = self.frontier.dequeue_from(&host).await? {
                         let spider = self.clone();
-                        let res_tx = res_tx.clone();
-                        
-                        tokio::spawn(async move {
-                            match spider.process_url(&url).await {
-                                Ok(new_links) => {
-                                    let _ = res_tx.send(new_links).await;
-                                }
-                                Err(e) => {
-                                    error!("Error processing {}: {}", url, e);
-                                    let _ = res_tx.send(vec![]).await;
-                                }
-                            }
+                        let url = lease.url.clone();
+                        let handle = workers.spawn(async move {
+                            let new_links = spider.process_url(&url).await?;
+                            spider.frontier.enqueue(&new_links).await?;
+                            Ok::<_, CrawlerError>(())
                         });
+                        leases.insert(handle.id(), lease);
                     }
                 }
+            }
+
+            // Pages scheduled for a recrawl or retry keep the crawl alive until they are due
+            if workers.is_empty()
+                && self.frontier.is_empty().await?
+                && self.recrawl.scheduled_len().await? == 0
+                && self.retry.scheduled_len().await? == 0
+            {
+                info!("Crawl finished.");
+                break;
+            }
+
+            tokio::select! {
+                _ = shutdown_rx.recv() => {
+                    info!("Spider shutting down...");
+                    break;
+                }
 
                 // Process results from workers
-                Some(new_links) = res_rx.recv() => {
-                    active_tasks -= 1;
-                    for link in new_links {
-                        let redis = self.redis.clone();
-                        let res_tx_worker = res_tx.clone();
-                        let link_clone = link.clone();
-                        
-                        // Async check for visited URL in Redis
-                        tokio::spawn(async move {
-                            let key = format!("visited:{}", link_clone);
-                            // fred v10: redis.exists() is an async method returning R: FromRedis
-                            // Borrow the key to avoid move
-                            match redis.exists::<i64, _>(&key).await {
-                                Ok(count) if count == 0 => {
-                                    // Not visited
-                                    if let Ok(_) = redis.set::<(), _, _>(&key, "1", None, None, false).await {
-                                        let _ = res_tx_worker.send(vec![link_clone]).await;
-                                    }
-                                }
-                                _ => {} // Already visited or error
+                Some(joined) = workers.join_next_with_id() => {
+                    match joined {
+                        Ok((id, result)) => {
+                            if let Some(lease) = leases.remove(&id) {
+                                self.settle(&lease, result).await?;
                             }
-                        });
+                        }
+                        Err(e) => {
+                            // The worker died mid-fetch, so its URL goes back to the frontier
+                            if let Some(lease) = leases.remove(&e.id()) {
+                                error!("Worker for {} failed: {}", lease.url, e);
+                                self.frontier.release(&lease).await?;
+                            }
+                        }
                     }
                 }
 
-                // Break if everything is done
-                else => {
-                    if active_tasks == 0 && pending_queue.is_empty() {
-                        info!("Crawl finished.");
-                        break;
+                _ = reaper.tick() => {
+                    self.frontier.requeue_expired().await?;
+                }
+
+                _ = recrawl_timer.tick() => {
+                    let due = self.recrawl.take_due(RECRAWL_BATCH).await?;
+                    if !due.is_empty() {
+                        let queued = self.frontier.enqueue_recrawl(&due).await?;
+                        debug!("Queued {} pages for recrawl", queued);
+                    }
+                    let retries = self.retry.take_due(RECRAWL_BATCH).await?;
+                    if !retries.is_empty() {
+                        let queued = self.frontier.enqueue_recrawl(&retries).await?;
+                        debug!("Queued {} pages for retry", queued);
+                    }
+                }
+
+                _ = sitemap_timer.tick(), if self.config.sitemaps => {
+                    // Only as many as can start now, the rest stay due for the next tick
+                    let free = self.sitemap_slots.available_permits().min(SITEMAP_BATCH);
+                    if free > 0 {
+                        for sitemap_url in self.sitemaps.take_due_news(free).await? {
+                            let Ok(permit) = self.sitemap_slots.clone().try_acquire_owned() else {
+                                self.sitemaps.watch_news(&sitemap_url).await?;
+                                continue;
+                            };
+                            let spider = self.clone();
+                            tokio::spawn(async move { spider.poll_news_sitemap(&sitemap_url, permit).await });
+                        }
+                    }
+                }
+
+                _ = feed_timer.tick(), if self.config.feeds => {
+                    for feed in self.storage.claim_due_feeds(FEED_BATCH, FEED_POLL_LEASE).await? {
+                        let spider = self.clone();
+                        tokio::spawn(async move { spider.poll_feed(feed).await });
                     }
                 }
+
+                _ = metrics_timer.tick() => {
+                    let robots = self.politeness.robots_metrics();
+                    info!(
+                        "robots.txt: {} fetched, {} coalesced, {} from shared cache",
+                        robots.fetches.load(Ordering::Relaxed),
+                        robots.coalesced.load(Ordering::Relaxed),
+                        robots.shared_hits.load(Ordering::Relaxed),
+                    );
+                    let protocols = self.fetcher.protocol_metrics();
+                    info!(
+                        "HTTP versions: {} HTTP/1.x, {} HTTP/2, {} fallbacks to HTTP/1.1",
+                        protocols.http1.load(Ordering::Relaxed),
+                        protocols.http2.load(Ordering::Relaxed),
+                        protocols.fallbacks.load(Ordering::Relaxed),
+                    );
+                }
+
+                // No host is ready right now, wait for one or for other workers
+                _ = tokio::time::sleep(idle_wait.unwrap_or_default()), if idle_wait.is_some() => {}
             }
         }
 
+        // Hand unfinished work back so the next run resumes it straight away
+        workers.abort_all();
+        for lease in leases.values() {
+            self.frontier.release(lease).await?;
+        }
+
         Ok(())
     }
 
+    /// Acks or puts back a finished URL depending on how it went. Transient
+    /// failures, storage errors included, are retried with backoff until the
+    /// attempt limit, then the URL is dead-lettered.
+    async fn settle(&self, lease: &Lease, result: Result<()>) -> Result<()> {
+        match result {
+            Ok(()) => {
+                self.frontier.ack(lease).await?;
+                self.retry.clear(&lease.url).await?;
+            }
+            // Put back before deferring, the release reschedules the host
+            Err(CrawlerError::RobotsUnavailable(retry_in)) => {
+                debug!("robots.txt unavailable, retrying {} in {:?}", lease.url, retry_in);
+                self.frontier.release(lease).await?;
+                self.frontier.defer_host(&lease.host, retry_in).await?;
+            }
+            Err(e) if e.is_transient() => {
+                match self.retry.failed(&lease.url).await? {
+                    RetryDecision::RetryIn { delay, attempt } => {
+                        warn!("Error processing {} (attempt {}), retrying in {:?}: {}", lease.url, attempt, delay, e);
+                    }
+                    RetryDecision::GiveUp { attempts } => {
+                        error!("Giving up on {} after {} attempts: {}", lease.url, attempts, e);
+                        // The database may be what keeps failing, which must not stop the crawl
+                        if let Err(e) = self.storage.dead_letter(&lease.url, &e.to_string(), attempts).await {
+                            error!("Could not dead-letter {}: {}", lease.url, e);
+                        }
+                    }
+                }
+                self.frontier.ack(lease).await?;
+            }
+            Err(e) => {
+                warn!("Error processing {}: {}", lease.url, e);
+                self.frontier.ack(lease).await?;
+                self.retry.clear(&lease.url).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Canonical, deduplicated forms of `urls`, so aliases share one visited key.
+    fn canonicalize_all(&self, urls: &[String]) -> Vec<String> {
+        let canonical: HashSet<String> = urls.iter()
+            .filter_map(|url| self.canonicalizer.canonicalize(url))
+            .collect();
+        canonical.into_iter().collect()
+    }
+
     pub fn shutdown(&self) {
         let _ = self.shutdown.send(());
     }
 
     async fn process_url(&self, url: &str) -> Result<Vec<String>> {
-        // Politeness check (Robots + Rate Limit)
-        if !self.politeness.check_and_wait(url).await {
-            debug!("Skipping disallowed or limited URL: {}", url);
-            return Ok(vec![]);
+        // Rate limiting already happened in the scheduler, only robots.txt is left
+        match self.politeness.access(url).await {
+            RobotsAccess::Allowed => {}
+            RobotsAccess::Disallowed => {
+                debug!("Skipping disallowed URL: {}", url);
+                return Ok(vec![]);
+            }
+            RobotsAccess::Unreachable(retry_in) => return Err(CrawlerError::RobotsUnavailable(retry_in)),
+        }
+
+        if self.config.sitemaps {
+            if let Some(host) = host_of(url) {
+                if self.sitemaps.claim_host(&host).await? {
+                    let spider = self.clone();
+                    let url = url.to_string();
+                    tokio::spawn(async move { spider.discover_sitemaps(&url).await });
+                }
+            }
         }
 
         debug!("Fetching: {}", url);
-        match self.fetcher.fetch(url).await {
-            Ok((status, body)) => {
-                if status.is_success() {
-                    let parsed = self.parser.parse(&body, url)?;
-                    self.storage.insert_document(url, &parsed.title, &parsed.text_content, None).await?;
-                    Ok(parsed.links)
-                } else {
-                    warn!("HTTP {}: {}", status, url);
-                    Ok(vec![])
+        let mut previous = self.storage.get_crawl_state(url).await?;
+        let validators = previous.as_ref().map(|p| p.validators.clone()).unwrap_or_default();
+        let fetched = self.fetcher.fetch_checked(url, &validators, &self.hop_check()).await;
+        if let Some(host) = host_of(url) {
+            self.politeness.record_fetch(&host, &fetched);
+        }
+        // Fetch errors are classified and retried by the caller
+        let fetched = fetched?;
+        // Each header on its own, a bot prefix in one does not carry over to the next
+        let header_directives = fetched.header_values("x-robots-tag").iter()
+            .map(|value| RobotsDirectives::from_header(value, self.config.bot_name()))
+            .fold(RobotsDirectives::default(), RobotsDirectives::merge);
+        let content_language = fetched.header("content-language");
+        let FetchResult { final_url, redirects, status, body, content_type, validators: fresh_validators, version, truncated, .. } = fetched;
+
+        if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+            return Err(CrawlerError::Http(status));
+        }
+
+        // A redirected page is stored under the URL it landed on, and the URLs
+        // that led there become its aliases
+        let page_url = self.canonicalizer.canonicalize(&final_url).unwrap_or_else(|| url.to_string());
+        let mut redirected_from = Vec::new();
+        if page_url != url {
+            let hops: Vec<String> = redirects.into_iter().map(|r| r.url).collect();
+            redirected_from = self.canonicalize_all(&hops);
+            redirected_from.retain(|hop| *hop != page_url);
+            debug!("{} redirected to {}", url, page_url);
+            // It has just been fetched, no need to queue it on its own
+            self.frontier.mark_visited(&page_url).await?;
+            previous = self.storage.get_crawl_state(&page_url).await?;
+        }
+
+        // Keyed on where the request landed, the validators only went to the first hop
+        if status == StatusCode::NOT_MODIFIED {
+            debug!("Not modified: {}", page_url);
+            let interval = self.schedule_recrawl(&page_url, previous.as_ref(), Some(false)).await?;
+            self.storage.touch_document(&page_url, &fresh_validators, interval).await?;
+            return Ok(vec![]);
+        }
+
+        let mime_type = extractor::mime_type(content_type.as_deref(), &body);
+        let Some(Extraction { page: parsed, encoding }) = self.extract(&page_url, &mime_type, body, content_type).await? else {
+            return Ok(vec![]);
+        };
+        let directives = parsed.robots.merge(header_directives);
+        let mut links = if directives.nofollow {
+            debug!("Not following links of {}", page_url);
+            vec![]
+        } else {
+            self.canonicalize_all(&parsed.links)
+        };
+        let http_version = format!("{:?}", version);
+        if truncated {
+            warn!("Body of {} cut off at {} bytes", page_url, self.config.max_body_bytes);
+        }
+
+        if self.config.feeds && !parsed.feeds.is_empty() {
+            let feeds = self.canonicalize_all(&parsed.feeds);
+            let poll_interval = Duration::from_secs(self.config.feed_poll_min_secs);
+            let registered = self.storage.register_feeds(&page_url, &feeds, poll_interval).await?;
+            if registered > 0 {
+                info!("Registered {} new feeds from {}", registered, page_url);
+            }
+        }
+
+        if directives.noindex {
+            // Kept on the recrawl schedule, the page may allow indexing again later
+            self.schedule_recrawl(&page_url, previous.as_ref(), None).await?;
+            if self.storage.remove_document(&page_url).await? {
+                info!("Removed {} from the index, it is now noindex", page_url);
+            } else {
+                debug!("Not indexing {}", page_url);
+            }
+            return Ok(links);
+        }
+
+        let text = if parsed.main_content.is_empty() { &parsed.text_content } else { &parsed.main_content };
+        let declared = [parsed.lang.as_deref(), content_language.as_deref(), parsed.metadata.open_graph.locale.as_deref()];
+        let identified = language::identify(text, &declared);
+        debug!("Language of {}: {:?}", page_url, identified);
+        let language = identified.map(|i| i.language);
+        let mixed_script = identified.is_some_and(|i| i.mixed_script);
+
+        // A canonical on another site is not trusted, or any page could take over another's row
+        let canonical = parsed.canonical_url.as_deref()
+            .and_then(|c| self.canonicalizer.canonicalize(c))
+            .filter(|c| {
+                let trusted = canonical::same_site(c, &page_url);
+                if !trusted {
+                    debug!("Ignoring canonical {} of {} on another site", c, page_url);
+                }
+                trusted
+            })
+            .unwrap_or_else(|| page_url.clone());
+
+        if canonical == page_url {
+            // Hashed without the page furniture, so a changed sidebar is not a changed page
+            let hash = content_hash(&parsed.main_content);
+            let changed = previous.as_ref()
+                .map(|p| p.content_hash.as_deref() != Some(hash.as_str()));
+            let interval = self.schedule_recrawl(&page_url, previous.as_ref(), changed).await?;
+
+            self.storage.insert_document(&NewDocument {
+                url: &page_url,
+                title: &parsed.title,
+                content: &parsed.text_content,
+                main_content: Some(&parsed.main_content),
+                metadata: Some(&parsed.metadata),
+                language,
+                mixed_script,
+                encoding,
+                content_type: Some(&mime_type),
+                http_version: Some(&http_version),
+                truncated,
+                aliases: &redirected_from,
+                validators: fresh_validators,
+                content_hash: Some(&hash),
+                recrawl_interval: Some(interval),
+            }).await?;
+        } else {
+            debug!("{} is an alias of {}", page_url, canonical);
+            let mut aliases = vec![page_url];
+            aliases.extend(redirected_from);
+            if self.storage.add_alias(&canonical, &aliases[0]).await? {
+                for alias in &aliases[1..] {
+                    self.storage.add_alias(&canonical, alias).await?;
                 }
+            } else {
+                // Index this copy under the canonical URL until that page is crawled itself.
+                // The validators belong to the alias, so the canonical page gets a full fetch.
+                self.storage.insert_document(&NewDocument {
+                    url: &canonical,
+                    title: &parsed.title,
+                    content: &parsed.text_content,
+                    main_content: Some(&parsed.main_content),
+                    metadata: Some(&parsed.metadata),
+                    language,
+                    mixed_script,
+                    encoding,
+                    content_type: Some(&mime_type),
+                    http_version: Some(&http_version),
+                    truncated,
+                    aliases: &aliases,
+                    ..Default::default()
+                }).await?;
             }
-            Err(e) => {
-                warn!("Fetch error {}: {}", url, e);
-                Ok(vec![])
+            // Queue the canonical page instead of the alias (a no-op if it was seen before)
+            links.push(canonical);
+        }
+
+        Ok(links)
+    }
+
+    /// Lets the fetcher follow a redirect to another host only if that host's
+    /// robots.txt allows the target, and once its rate limit has a slot.
+    fn hop_check(&self) -> Box<HopCheck> {
+        let politeness = self.politeness.clone();
+        Box::new(move |hop: String| {
+            let politeness = politeness.clone();
+            Box::pin(async move {
+                match politeness.access(&hop).await {
+                    RobotsAccess::Allowed => {
+                        if let Some(host) = host_of(&hop) {
+                            politeness.acquire(&host).await;
+                        }
+                        Ok(true)
+                    }
+                    RobotsAccess::Disallowed => Ok(false),
+                    RobotsAccess::Unreachable(retry_in) => Err(CrawlerError::RobotsUnavailable(retry_in)),
+                }
+            })
+        })
+    }
+
+    /// Runs the extractor registered for `mime_type` on a blocking thread,
+    /// since PDF and large HTML bodies take a while. Bodies of other types are
+    /// recorded as skipped and yield `None`.
+    async fn extract(&self, url: &str, mime_type: &str, body: Vec<u8>, content_type: Option<String>) -> Result<Option<Extraction>> {
+        let Some(extractor) = self.content.extractor_for(mime_type) else {
+            debug!("Skipping {} ({})", url, mime_type);
+            self.storage.record_skipped(url, mime_type).await?;
+            return Ok(None);
+        };
+
+        let page_url = url.to_string();
+        let extraction = task::spawn_blocking(move || extractor.extract(&body, content_type.as_deref(), &page_url))
+            .await
+            // Some malformed PDFs make the extractor panic
+            .map_err(|e| CrawlerError::Parse(format!("Extractor failed on {}: {}", url, e)))??;
+        Ok(Some(extraction))
+    }
+
+    /// Reads the sitemaps of the site `url` belongs to. Runs beside the crawl
+    /// loop, since a large site can take thousands of rate-limited fetches.
+    async fn discover_sitemaps(&self, url: &str) {
+        let Ok(_permit) = self.sitemap_slots.acquire().await else {
+            return;
+        };
+        for root in self.sitemaps.roots(url, &self.politeness).await {
+            if let Err(e) = self.read_sitemap(&root).await {
+                warn!("Error reading sitemap {}: {}", root, e);
+            }
+        }
+    }
+
+    /// Polls a news sitemap taken off the schedule. A failed poll puts it
+    /// back, or one bad response would stop its polling for good.
+    async fn poll_news_sitemap(&self, sitemap_url: &str, _permit: OwnedSemaphorePermit) {
+        if let Err(e) = self.read_sitemap(sitemap_url).await {
+            warn!("Error reading sitemap {}: {}", sitemap_url, e);
+            if let Err(e) = self.sitemaps.watch_news(sitemap_url).await {
+                warn!("Could not reschedule news sitemap {}: {}", sitemap_url, e);
             }
         }
     }
+
+    async fn read_sitemap(&self, sitemap_url: &str) -> Result<()> {
+        let found = self.sitemaps.read(sitemap_url, &self.politeness).await?;
+        let queued = self.enqueue_sitemap_entries(&found.entries).await?;
+        for news_sitemap in &found.news_sitemaps {
+            self.sitemaps.watch_news(news_sitemap).await?;
+        }
+        info!("Sitemap {}: {} URLs, {} new", sitemap_url, found.entries.len(), queued);
+        Ok(())
+    }
+
+    /// Fetches a feed and queues its unseen articles at high priority. Feeds
+    /// with new items are polled more often, quiet ones less.
+    async fn poll_feed(&self, feed: FeedState) {
+        let result = async {
+            let mut new_items = 0;
+            let mut validators = feed.validators.clone();
+            if self.politeness.is_allowed(&feed.url).await {
+                if let Some(host) = host_of(&feed.url) {
+                    self.politeness.acquire(&host).await;
+                }
+                let fetched = self.fetcher.fetch_conditional(&feed.url, &feed.validators).await;
+                if let Some(host) = host_of(&feed.url) {
+                    self.politeness.record_fetch(&host, &fetched);
+                }
+                let fetched = fetched?;
+                if fetched.status.is_success() {
+                    let links = self.canonicalize_all(&feed::item_links(&fetched.body, &feed.url)?);
+                    new_items = self.frontier.enqueue_with_priority(&links, Priority::High).await?;
+                } else if fetched.status != StatusCode::NOT_MODIFIED {
+                    warn!("HTTP {}: feed {}", fetched.status, feed.url);
+                }
+                validators = fetched.validators;
+            }
+
+            let min = Duration::from_secs(self.config.feed_poll_min_secs);
+            let max = Duration::from_secs(self.config.feed_poll_max_secs).max(min);
+            let interval = if new_items > 0 {
+                feed.poll_interval / 2
+            } else {
+                feed.poll_interval.mul_f64(1.5)
+            };
+            let interval = interval.clamp(min, max);
+
+            self.storage.record_feed_poll(&feed.url, &validators, interval, new_items).await?;
+            if new_items > 0 {
+                info!("Feed {}: {} new articles", feed.url, new_items);
+            }
+            Ok::<_, CrawlerError>(())
+        };
+        if let Err(e) = result.await {
+            warn!("Error polling feed {}: {}", feed.url, e);
+        }
+    }
+
+    /// Queues unseen sitemap URLs. News articles and recently modified pages
+    /// jump the queue, pages the site itself ranks low wait behind the rest.
+    async fn enqueue_sitemap_entries(&self, entries: &[SitemapEntry]) -> Result<usize> {
+        let fresh_since = Utc::now() - FRESH_SITEMAP_ENTRY;
+        let mut by_priority: BTreeMap<Priority, HashSet<String>> = BTreeMap::new();
+        for entry in entries {
+            let Some(url) = self.canonicalizer.canonicalize(&entry.loc) else {
+                continue;
+            };
+            let priority = if entry.news || entry.lastmod.is_some_and(|t| t >= fresh_since) {
+                Priority::High
+            } else if entry.priority.is_some_and(|p| p < 0.3) {
+                Priority::Low
+            } else {
+                Priority::Normal
+            };
+            by_priority.entry(priority).or_default().insert(url);
+        }
+
+        let mut queued = 0;
+        for (priority, urls) in by_priority {
+            let urls: Vec<String> = urls.into_iter().collect();
+            queued += self.frontier.enqueue_with_priority(&urls, priority).await?;
+        }
+        Ok(queued)
+    }
+
+    /// Works out when `url` is crawled next and puts it on the recrawl schedule.
+    async fn schedule_recrawl(&self, url: &str, previous: Option<&CrawlState>, changed: Option<bool>) -> Result<Duration> {
+        let host = host_of(url).unwrap_or_default();
+        let previous_interval = previous.and_then(|p| p.recrawl_interval);
+        let interval = self.recrawl.observe(&host, previous_interval, changed).await?;
+        self.recrawl.schedule(url, interval).await?;
+        Ok(interval)
+    }
 }
 
 impl Clone for Spider {
@@ -163,10 +662,15 @@ impl Clone for Spider {
         Self {
             config: self.config.clone(),
             fetcher: self.fetcher.clone(),
-            parser: self.parser.clone(),
+            content: self.content.clone(),
+            canonicalizer: self.canonicalizer.clone(),
             storage: self.storage.clone(),
             politeness: self.politeness.clone(),
-            redis: self.redis.clone(),
+            frontier: self.frontier.clone(),
+            recrawl: self.recrawl.clone(),
+            sitemaps: self.sitemaps.clone(),
+            sitemap_slots: self.sitemap_slots.clone(),
+            retry: self.retry.clone(),
             shutdown: self.shutdown.clone(),
         }
     }
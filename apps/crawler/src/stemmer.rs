@@ -20,7 +20,7 @@ impl NepaliNlp {
         }
 
         let mut sorted_suffixes = SUFFIXES.to_vec();
-        sorted_suffixes.sort_by(|a, b| b.len().cmp(&a.len()));
+        sorted_suffixes.sort_by_key(|s| std::cmp::Reverse(s.len()));
 
         let mut stemmed = word.to_string();
         
@@ -60,7 +60,7 @@ impl NepaliNlp {
         // Append transliterated text for Latin-script search support
         let transliterated = Self::transliterate(text);
         if !transliterated.is_empty() {
-            processed.push_str(" ");
+            processed.push(' ');
             processed.push_str(&transliterated);
         }
         
@@ -1,6 +1,57 @@
+use chrono::{DateTime, Utc};
 use sqlx::postgres::{PgPool, PgPoolOptions};
-use sqlx::Error;
-use crate::stemmer::NepaliNlp;
+use sqlx::{Error, Row};
+use std::time::Duration;
+use crate::fetcher::Validators;
+use crate::language::{self, Language};
+use crate::metadata::PageMetadata;
+
+/// A crawled page as it is written to `documents`.
+#[derive(Debug, Default)]
+pub struct NewDocument<'a> {
+    /// Canonical URL the row is keyed on
+    pub url: &'a str,
+    pub title: &'a str,
+    pub content: &'a str,
+    /// The article without page furniture; searched instead of `content` when present
+    pub main_content: Option<&'a str>,
+    /// Also picks the pipeline `searchable_text` is built with
+    pub language: Option<Language>,
+    /// Devanagari and Latin text both make up a good part of the page
+    pub mixed_script: bool,
+    /// Charset the page was decoded from
+    pub encoding: Option<&'a str>,
+    /// Media type of the response, e.g. `application/pdf`
+    pub content_type: Option<&'a str>,
+    /// HTTP version the page was fetched over, e.g. `HTTP/2.0`
+    pub http_version: Option<&'a str>,
+    /// The body was cut off at the size limit, so only the start of the page is indexed
+    pub truncated: bool,
+    /// Description, authors, dates and the like the page declares about itself
+    pub metadata: Option<&'a PageMetadata>,
+    /// Other URLs that pointed to this one; rows stored under them earlier are removed
+    pub aliases: &'a [String],
+    /// Cache validators of the response the content came from
+    pub validators: Validators,
+    pub content_hash: Option<&'a str>,
+    pub recrawl_interval: Option<Duration>,
+}
+
+/// Crawl bookkeeping kept with a stored document.
+#[derive(Debug, Default)]
+pub struct CrawlState {
+    pub validators: Validators,
+    pub content_hash: Option<String>,
+    pub recrawl_interval: Option<Duration>,
+}
+
+/// A registered feed as it is claimed for polling.
+#[derive(Debug)]
+pub struct FeedState {
+    pub url: String,
+    pub validators: Validators,
+    pub poll_interval: Duration,
+}
 
 #[derive(Clone)]
 pub struct Storage {
@@ -17,39 +68,317 @@ impl Storage {
         Ok(Self { pool })
     }
 
-    pub async fn insert_document(&self, url: &str, title: &str, content: &str, language: Option<&str>) -> Result<(), Error> {
-        let searchable_text = NepaliNlp::process_text(content);
-        
+    /// Upserts a document keyed on its canonical URL.
+    pub async fn insert_document(&self, doc: &NewDocument<'_>) -> Result<(), Error> {
+        let text = doc.main_content.filter(|m| !m.is_empty()).unwrap_or(doc.content);
+        let searchable_text = language::searchable_text(text, doc.language);
+        let interval_secs = doc.recrawl_interval.map(|i| i.as_secs() as i32);
+        let metadata_json = doc.metadata.and_then(|m| serde_json::to_string(m).ok());
+        let authors = doc.metadata.map(|m| m.authors.as_slice()).unwrap_or_default();
+        let mut tx = self.pool.begin().await?;
+
         // Upsert based on URL
         // Using sqlx::query instead of sqlx::query! to avoid build-time DB requirement
         sqlx::query(
             r#"
-            INSERT INTO documents (url, title, content_text, searchable_text, language)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO documents (
+                url, title, content_text, searchable_text, language, aliases, etag, last_modified,
+                content_hash, recrawl_interval_secs, next_crawl_at, check_count, change_count, encoding,
+                content_type, http_version, truncated, main_content, description, authors, published_at,
+                modified_at, image_url, metadata, mixed_script
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW() + make_interval(secs => $10), 1, 1, $11, $12, $13, $14,
+                $15, $16, $17, $18, $19, $20, $21::jsonb, $22
+            )
             ON CONFLICT (url) 
             DO UPDATE SET 
                 title = EXCLUDED.title,
                 content_text = EXCLUDED.content_text,
                 searchable_text = EXCLUDED.searchable_text,
                 language = EXCLUDED.language,
+                aliases = ARRAY(SELECT DISTINCT unnest(documents.aliases || EXCLUDED.aliases)),
+                etag = EXCLUDED.etag,
+                last_modified = EXCLUDED.last_modified,
+                check_count = documents.check_count + 1,
+                change_count = documents.change_count
+                    + CASE WHEN documents.content_hash IS DISTINCT FROM EXCLUDED.content_hash THEN 1 ELSE 0 END,
+                content_hash = EXCLUDED.content_hash,
+                recrawl_interval_secs = COALESCE(EXCLUDED.recrawl_interval_secs, documents.recrawl_interval_secs),
+                next_crawl_at = COALESCE(EXCLUDED.next_crawl_at, documents.next_crawl_at),
+                encoding = EXCLUDED.encoding,
+                content_type = EXCLUDED.content_type,
+                http_version = EXCLUDED.http_version,
+                truncated = EXCLUDED.truncated,
+                main_content = EXCLUDED.main_content,
+                description = EXCLUDED.description,
+                authors = EXCLUDED.authors,
+                published_at = EXCLUDED.published_at,
+                modified_at = EXCLUDED.modified_at,
+                image_url = EXCLUDED.image_url,
+                metadata = EXCLUDED.metadata,
+                mixed_script = EXCLUDED.mixed_script,
                 crawled_at = NOW()
             "#,
         )
-        .bind(url)
-        .bind(title)
-        .bind(content)
+        .bind(doc.url)
+        .bind(doc.title)
+        .bind(doc.content)
         .bind(searchable_text)
-        .bind(language)
-        .execute(&self.pool)
+        .bind(doc.language.map(Language::code))
+        .bind(doc.aliases)
+        .bind(&doc.validators.etag)
+        .bind(&doc.validators.last_modified)
+        .bind(doc.content_hash)
+        .bind(interval_secs)
+        .bind(doc.encoding)
+        .bind(doc.content_type)
+        .bind(doc.http_version)
+        .bind(doc.truncated)
+        .bind(doc.main_content)
+        .bind(doc.metadata.and_then(PageMetadata::summary))
+        .bind(authors)
+        .bind(doc.metadata.and_then(|m| m.published_at))
+        .bind(doc.metadata.and_then(|m| m.modified_at))
+        .bind(doc.metadata.and_then(|m| m.image.as_deref()))
+        .bind(metadata_json)
+        .bind(doc.mixed_script)
+        .execute(&mut *tx)
         .await?;
 
+        if !doc.aliases.is_empty() {
+            sqlx::query("DELETE FROM documents WHERE url = ANY($1)")
+                .bind(doc.aliases)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
         Ok(())
     }
+
+    /// Records `alias` on the document stored under `canonical_url`.
+    /// Returns false if no such document exists yet.
+    pub async fn add_alias(&self, canonical_url: &str, alias: &str) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE documents
+            SET aliases = CASE WHEN $2 = ANY(aliases) THEN aliases ELSE array_append(aliases, $2) END
+            WHERE url = $1
+            "#,
+        )
+        .bind(canonical_url)
+        .bind(alias)
+        .execute(&mut *tx)
+        .await?;
+
+        if updated.rows_affected() > 0 {
+            sqlx::query("DELETE FROM documents WHERE url = $1")
+                .bind(alias)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(updated.rows_affected() > 0)
+    }
     
+    /// Records a URL whose content type has no extractor, so it can be looked into later.
+    pub async fn record_skipped(&self, url: &str, content_type: &str) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO skipped_urls (url, content_type)
+            VALUES ($1, $2)
+            ON CONFLICT (url)
+            DO UPDATE SET content_type = EXCLUDED.content_type, skipped_at = NOW()
+            "#,
+        )
+        .bind(url)
+        .bind(content_type)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes a page from the index, e.g. once it asks not to be indexed.
+    /// Returns false if it was not stored.
+    pub async fn remove_document(&self, url: &str) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM documents WHERE url = $1")
+            .bind(url)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Parks a URL that kept failing, with the last error, for inspection.
+    pub async fn dead_letter(&self, url: &str, error: &str, attempts: u32) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO dead_letters (url, error, attempts)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (url)
+            DO UPDATE SET error = EXCLUDED.error, attempts = EXCLUDED.attempts, failed_at = NOW()
+            "#,
+        )
+        .bind(url)
+        .bind(error)
+        .bind(attempts as i32)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Validators, content hash and recrawl interval from the last crawl of `url`.
+    pub async fn get_crawl_state(&self, url: &str) -> Result<Option<CrawlState>, Error> {
+        let row = sqlx::query(
+            "SELECT etag, last_modified, content_hash, recrawl_interval_secs FROM documents WHERE url = $1"
+        )
+        .bind(url)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| CrawlState {
+            validators: Validators {
+                etag: row.get("etag"),
+                last_modified: row.get("last_modified"),
+            },
+            content_hash: row.get("content_hash"),
+            recrawl_interval: row.get::<Option<i32>, _>("recrawl_interval_secs")
+                .map(|secs| Duration::from_secs(secs.max(0) as u64)),
+        }))
+    }
+
+    /// Marks an unchanged document (HTTP 304) as freshly crawled without rewriting it.
+    /// Validators the server sent along replace the stored ones.
+    pub async fn touch_document(&self, url: &str, validators: &Validators, recrawl_interval: Duration) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE documents
+            SET crawled_at = NOW(),
+                etag = COALESCE($2, etag),
+                last_modified = COALESCE($3, last_modified),
+                recrawl_interval_secs = $4,
+                next_crawl_at = NOW() + make_interval(secs => $4),
+                check_count = check_count + 1
+            WHERE url = $1
+            "#,
+        )
+        .bind(url)
+        .bind(&validators.etag)
+        .bind(&validators.last_modified)
+        .bind(recrawl_interval.as_secs() as i32)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// When every stored document is due for a recrawl. Documents crawled
+    /// before recrawl scheduling existed fall back to `default_interval_secs`.
+    pub async fn recrawl_due_times(&self, default_interval_secs: i64) -> Result<Vec<(String, DateTime<Utc>)>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT url, COALESCE(
+                next_crawl_at,
+                crawled_at + make_interval(secs => COALESCE(recrawl_interval_secs, $1)),
+                NOW()
+            ) AS due_at
+            FROM documents
+            "#,
+        )
+        .bind(default_interval_secs as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.get("url"), row.get("due_at"))).collect())
+    }
+
+    /// Adds feeds advertised by `site_url` to the registry; known feeds are left alone.
+    /// New feeds are due for a poll straight away. Returns how many were new.
+    pub async fn register_feeds(&self, site_url: &str, feeds: &[String], poll_interval: Duration) -> Result<u64, Error> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO feeds (url, site_url, poll_interval_secs)
+            SELECT unnest($1::TEXT[]), $2, $3
+            ON CONFLICT (url) DO NOTHING
+            "#,
+        )
+        .bind(feeds)
+        .bind(site_url)
+        .bind(poll_interval.as_secs() as i32)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(inserted.rows_affected())
+    }
+
+    /// Claims up to `limit` feeds that are due for a poll. Their next poll is
+    /// pushed `lease` into the future, so a poller that dies is retried later
+    /// and other workers skip them meanwhile.
+    pub async fn claim_due_feeds(&self, limit: i64, lease: Duration) -> Result<Vec<FeedState>, Error> {
+        let rows = sqlx::query(
+            r#"
+            UPDATE feeds
+            SET next_poll_at = NOW() + make_interval(secs => $2)
+            WHERE url IN (
+                SELECT url FROM feeds
+                WHERE next_poll_at <= NOW()
+                ORDER BY next_poll_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING url, etag, last_modified, poll_interval_secs
+            "#,
+        )
+        .bind(limit)
+        .bind(lease.as_secs_f64())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| FeedState {
+            url: row.get("url"),
+            validators: Validators {
+                etag: row.get("etag"),
+                last_modified: row.get("last_modified"),
+            },
+            poll_interval: Duration::from_secs(row.get::<i32, _>("poll_interval_secs").max(0) as u64),
+        }).collect())
+    }
+
+    /// Records a finished poll and schedules the next one.
+    pub async fn record_feed_poll(&self, url: &str, validators: &Validators, poll_interval: Duration, new_items: usize) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE feeds
+            SET last_polled_at = NOW(),
+                next_poll_at = NOW() + make_interval(secs => $4),
+                poll_interval_secs = $4,
+                etag = COALESCE($2, etag),
+                last_modified = COALESCE($3, last_modified),
+                items_found = items_found + $5
+            WHERE url = $1
+            "#,
+        )
+        .bind(url)
+        .bind(&validators.etag)
+        .bind(&validators.last_modified)
+        .bind(poll_interval.as_secs() as i32)
+        .bind(new_items as i32)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     // Check if URL exists (frontier optimization)
     pub async fn url_exists(&self, url: &str) -> Result<bool, Error> {
         let row = sqlx::query(
-            "SELECT 1 FROM documents WHERE url = $1 LIMIT 1"
+            "SELECT 1 FROM documents WHERE url = $1 OR $1 = ANY(aliases) LIMIT 1"
         )
         .bind(url)
         .fetch_optional(&self.pool)
@@ -0,0 +1,5 @@
+-- Other URLs (AMP, print, category paths) that declared a document as their rel=canonical
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS aliases TEXT[] NOT NULL DEFAULT '{}';
+
+-- Index for alias lookups (crawler dedup)
+CREATE INDEX IF NOT EXISTS documents_aliases_idx ON documents USING GIN (aliases);
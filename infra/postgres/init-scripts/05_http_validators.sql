@@ -0,0 +1,3 @@
+-- Cache validators from the last full fetch, sent back as If-None-Match / If-Modified-Since on recrawl
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS etag TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS last_modified TEXT;
@@ -0,0 +1,9 @@
+-- Adaptive recrawl bookkeeping: content hash of the last fetch, how often the page
+-- was checked and found changed, and when it is due again
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS content_hash TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS check_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS change_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS recrawl_interval_secs INTEGER;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS next_crawl_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX IF NOT EXISTS documents_next_crawl_idx ON documents(next_crawl_at);
@@ -0,0 +1,2 @@
+-- Charset each page was decoded from, e.g. UTF-8 or windows-1252
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS encoding TEXT;
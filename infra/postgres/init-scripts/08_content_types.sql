@@ -0,0 +1,11 @@
+-- Media type each document was extracted from, e.g. text/html or application/pdf
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS content_type TEXT;
+
+-- Responses whose content type has no extractor
+CREATE TABLE IF NOT EXISTS skipped_urls (
+    url TEXT PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    skipped_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS skipped_urls_content_type_idx ON skipped_urls(content_type);
@@ -0,0 +1,14 @@
+-- RSS / Atom feeds discovered on crawled pages, polled for new articles
+CREATE TABLE IF NOT EXISTS feeds (
+    url TEXT PRIMARY KEY,
+    site_url TEXT NOT NULL, -- Page the feed was advertised on
+    etag TEXT,
+    last_modified TEXT,
+    poll_interval_secs INTEGER NOT NULL,
+    next_poll_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_polled_at TIMESTAMP WITH TIME ZONE,
+    items_found INTEGER NOT NULL DEFAULT 0, -- New article links found over all polls
+    discovered_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS feeds_next_poll_idx ON feeds(next_poll_at);
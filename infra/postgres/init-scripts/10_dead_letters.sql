@@ -0,0 +1,9 @@
+-- URLs that still failed after every retry, with the last error
+CREATE TABLE IF NOT EXISTS dead_letters (
+    url TEXT PRIMARY KEY,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS dead_letters_failed_at_idx ON dead_letters(failed_at);
@@ -0,0 +1,2 @@
+-- HTTP version each document was fetched over, e.g. HTTP/1.1 or HTTP/2.0
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS http_version TEXT;
@@ -0,0 +1,2 @@
+-- Documents whose body was cut off at the size limit and are only partially indexed
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS truncated BOOLEAN NOT NULL DEFAULT FALSE;
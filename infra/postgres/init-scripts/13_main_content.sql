@@ -0,0 +1,2 @@
+-- Article text without navigation, footers and ads; searchable_text is built from it
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS main_content TEXT;
@@ -0,0 +1,10 @@
+-- What pages declare about themselves: description, authors, dates and preview image.
+-- The full OpenGraph / Twitter card / JSON-LD fields are kept in metadata.
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS description TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS authors TEXT[] DEFAULT '{}';
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS published_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS modified_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS image_url TEXT;
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS metadata JSONB;
+
+CREATE INDEX IF NOT EXISTS documents_published_at_idx ON documents(published_at);
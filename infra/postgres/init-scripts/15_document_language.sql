@@ -0,0 +1,5 @@
+-- Pages with a good part of their text in both Devanagari and Latin script
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS mixed_script BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Searches restricted to one language
+CREATE INDEX IF NOT EXISTS documents_language_idx ON documents(language);